@@ -17,6 +17,8 @@ pub enum Error {
     InvalidRequestId,
     InternalError,
     MissingPermission { permission: Permission },
+    InvalidPermissions,
+    RateLimited { retry_after: i64 },
 
     // Authentication errors
     InvalidToken,
@@ -25,10 +27,31 @@ pub enum Error {
     // Message errors
     MessageTooLong,
     MessageEmpty,
+    InvalidFormatting,
+    NotAuthor,
+    PinLimitReached,
+    InvalidFilter,
+    InvalidSchedule,
+    InvalidNonce,
+    NoncePending,
+    SlowmodeActive { retry_after: i64 },
+
+    // Attachment errors
+    FileTooLarge { max_size: i64 },
+    InvalidUpload,
+
+    // Embed errors
+    InvalidEmbed,
+
+    // Encryption errors
+    EncryptionRequired,
+    NotEncrypted,
+    InvalidKeyBundle,
 
     // Space errors
     NameTooLong,
     NameEmpty,
+    DescriptionTooLong,
 
     // Invite errors
     InvalidInvite,
@@ -37,12 +60,20 @@ pub enum Error {
 
     // Channel errors
     ChannelFull,
+    InvalidChannelOrder,
+    NotOwner,
+    InvalidSlowmode,
 
     // User errors
     Blocked,
     AlreadyFriends,
     AlreadyRequested,
     NotFriends,
+    DirectMessagesRestricted,
+
+    // Emoji errors
+    InvalidName,
+    EmojiLimitReached,
 
     // Call errors
     AlreadyExists,
@@ -61,20 +92,51 @@ impl fmt::Display for Error {
             Error::MissingPermission { permission } => {
                 write!(f, "Missing permission: {permission:?}")
             }
+            Error::InvalidPermissions => write!(f, "Invalid permissions"),
+            Error::RateLimited { retry_after } => {
+                write!(f, "Rate limited, retry in {retry_after}ms")
+            }
             Error::InvalidToken => write!(f, "Invalid token"),
             Error::NotAuthenticated => write!(f, "Not authenticated"),
             Error::MessageTooLong => write!(f, "Message too long"),
             Error::MessageEmpty => write!(f, "Message empty"),
+            Error::NotAuthor => write!(f, "Not the author of this message"),
+            Error::PinLimitReached => write!(f, "Pin limit reached"),
+            Error::InvalidFilter => write!(f, "Invalid filter"),
+            Error::InvalidFormatting => write!(f, "Invalid formatting"),
+            Error::InvalidNonce => write!(f, "Invalid nonce"),
+            Error::NoncePending => write!(f, "A message with this nonce is still being sent"),
+            Error::SlowmodeActive { retry_after } => {
+                write!(f, "Slow mode is active, retry in {retry_after}ms")
+            }
+            Error::InvalidSchedule => write!(f, "Invalid schedule"),
+            Error::FileTooLarge { max_size } => {
+                write!(f, "File too large, maximum size is {max_size} bytes")
+            }
+            Error::InvalidUpload => write!(f, "Invalid upload"),
+            Error::InvalidEmbed => write!(f, "Invalid embed"),
+            Error::EncryptionRequired => write!(f, "Encryption required"),
+            Error::NotEncrypted => write!(f, "Channel is not encrypted"),
+            Error::InvalidKeyBundle => write!(f, "Invalid key bundle"),
             Error::NameTooLong => write!(f, "Name too long"),
             Error::NameEmpty => write!(f, "Name empty"),
+            Error::DescriptionTooLong => write!(f, "Description too long"),
             Error::InvalidInvite => write!(f, "Invalid invite"),
             Error::InviteExpired => write!(f, "Invite expired"),
             Error::InviteAlreadyUsed => write!(f, "Invite already used"),
             Error::ChannelFull => write!(f, "Channel full"),
+            Error::InvalidChannelOrder => write!(f, "Invalid channel order"),
+            Error::NotOwner => write!(f, "Not the owner"),
+            Error::InvalidSlowmode => write!(f, "Invalid slow mode duration"),
             Error::Blocked => write!(f, "Blocked"),
             Error::AlreadyFriends => write!(f, "Already friends"),
             Error::AlreadyRequested => write!(f, "Already requested"),
             Error::NotFriends => write!(f, "Not friends"),
+            Error::DirectMessagesRestricted => {
+                write!(f, "User does not accept direct messages from you")
+            }
+            Error::InvalidName => write!(f, "Invalid name"),
+            Error::EmojiLimitReached => write!(f, "Emoji limit reached"),
             Error::AlreadyExists => write!(f, "Already exists"),
             Error::CallLimitReached => write!(f, "Call limit reached"),
         }
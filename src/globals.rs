@@ -3,4 +3,24 @@ use lazy_static::lazy_static;
 lazy_static! {
     pub static ref HEARTBEAT_INTERVAL: u64 = 30000;
     pub static ref HEARTBEAT_TIMEOUT: u64 = 60000;
+    pub static ref TYPING_TIMEOUT: u64 = 8000;
+    pub static ref TYPING_INTERVAL: u64 = 3000;
+    pub static ref NONCE_WINDOW: u64 = 5 * 60 * 1000;
+    // How long a scheduled message may take to send before another instance retries it
+    pub static ref SCHEDULED_CLAIM_LEASE: i64 = 60 * 1000;
+    pub static ref MAX_NONCE_LENGTH: usize = 64;
+    // Measured in grapheme clusters, with a byte limit on top
+    pub static ref MAX_MESSAGE_LENGTH: usize = 4096;
+    pub static ref MAX_MESSAGE_BYTES: usize = 131072;
+    pub static ref MAX_FORMATTING_DEPTH: usize = 32;
+    pub static ref MAX_CHUNK_SIZE: usize = 1024 * 1024;
+    pub static ref MAX_ATTACHMENTS: usize = 10;
+    pub static ref MAX_CIPHERTEXT_SIZE: usize = 16384;
+    pub static ref MAX_BULK_DELETE: i64 = 1000;
+    // In seconds
+    pub static ref MAX_SCHEDULE_AHEAD: i64 = 365 * 24 * 60 * 60;
+    pub static ref MAX_MESSAGE_LIFETIME: i64 = 30 * 24 * 60 * 60;
+    pub static ref MAX_PREKEY_CLAIMS: i64 = 10;
+    pub static ref PREKEY_CLAIM_WINDOW: u64 = 60 * 60 * 1000;
+    pub static ref MAX_SLOWMODE: u32 = 6 * 60 * 60;
 }
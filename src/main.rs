@@ -1,17 +1,24 @@
-#![allow(dead_code)]
+#![allow(dead_code, clippy::unnecessary_sort_by)]
 
 pub mod errors;
 pub mod globals;
 pub mod methods;
 pub mod services;
 
+use std::env;
+
 use services::database;
+use services::development;
+use services::embeds;
+use services::redis;
 use services::socket;
+use services::storage;
+use services::tasks;
 // use services::webrtc;
 
-use log::info;
+use log::{error, info};
 
-use crate::services::environment::LISTEN_ADDRESS;
+use crate::services::environment::{DEV_MODE, LISTEN_ADDRESS, STORAGE_BACKEND};
 
 #[async_std::main]
 async fn main() {
@@ -20,10 +27,59 @@ async fn main() {
     dotenvy::dotenv().ok();
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
+    // Development helpers, only available with DEV_MODE enabled:
+    //   harmony token <user id>   print a token for any user
+    //   harmony seed              populate the database with demo data
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("token") => {
+            let user_id = args
+                .get(2)
+                .map(String::as_str)
+                .unwrap_or(development::DEMO_USER_ID);
+            match development::mint_token(user_id, 30 * 24 * 60 * 60 * 1000) {
+                Ok(token) => println!("{token}"),
+                Err(_) => error!("Tokens can only be minted with DEV_MODE enabled"),
+            }
+            return;
+        }
+        Some("seed") => {
+            if !*DEV_MODE {
+                error!("Seeding is only available with DEV_MODE enabled");
+                return;
+            }
+            database::connect().await;
+            development::seed().await.expect("Failed to seed database");
+            return;
+        }
+        _ => {}
+    }
+
     database::connect().await;
     info!("Connected to database");
 
+    redis::connect().await;
+    info!("Connected to Redis");
+
+    storage::connect();
+    info!("Using {} storage backend", *STORAGE_BACKEND);
+
     // run DB migrations as necessary
+    database::create_indexes()
+        .await
+        .expect("Failed to create database indexes");
+
+    if *DEV_MODE {
+        development::seed().await.expect("Failed to seed database");
+        let token = development::mint_token(development::DEMO_USER_ID, 24 * 60 * 60 * 1000)
+            .expect("Failed to mint token");
+        info!("Development mode enabled, demo user token: {token}");
+    }
+
+    tasks::spawn_thread_archiver();
+    tasks::spawn_message_scheduler();
+    tasks::spawn_upload_cleaner();
+    embeds::spawn_worker();
 
     // webrtc::create_workers().await;
     // println!("SFU workers have spawned");
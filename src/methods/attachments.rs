@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    globals::MAX_CHUNK_SIZE,
+    services::{
+        database::{
+            attachments::{Attachment, MessageAttachment},
+            channels::Channel,
+            emojis::Emoji,
+        },
+        socket::RpcClient,
+    },
+};
+
+use super::{authentication::check_authenticated, messages::check_can_view, Respond, Response};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUploadMethod {
+    filename: String,
+    size: i64,
+    content_type: Option<String>,
+}
+
+#[async_trait]
+impl Respond for CreateUploadMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let attachment = Attachment::create(
+            user.id.clone(),
+            self.filename.clone(),
+            self.size,
+            self.content_type.clone(),
+        )
+        .await?;
+        Ok(Response::CreateUpload(CreateUploadResponse {
+            attachment_id: attachment.id,
+            chunk_size: *MAX_CHUNK_SIZE,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUploadResponse {
+    attachment_id: String,
+    chunk_size: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadChunkMethod {
+    attachment_id: String,
+    offset: i64,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+}
+
+#[async_trait]
+impl Respond for UploadChunkMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        if self.data.len() > *MAX_CHUNK_SIZE {
+            return Err(Error::InvalidUpload);
+        }
+        let attachment = Attachment::get(&self.attachment_id).await?;
+        if attachment.uploader_id != user.id {
+            return Err(Error::NotFound);
+        }
+        let attachment = attachment
+            .append_chunk(self.offset, self.data.clone())
+            .await?;
+        Ok(Response::UploadChunk(UploadChunkResponse {
+            received: attachment.received,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadChunkResponse {
+    received: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteUploadMethod {
+    attachment_id: String,
+}
+
+#[async_trait]
+impl Respond for CompleteUploadMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let attachment = Attachment::get(&self.attachment_id).await?;
+        if attachment.uploader_id != user.id {
+            return Err(Error::NotFound);
+        }
+        let attachment = attachment.complete().await?;
+        Ok(Response::CompleteUpload(CompleteUploadResponse {
+            attachment: attachment.into(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteUploadResponse {
+    attachment: MessageAttachment,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAttachmentMethod {
+    attachment_id: String,
+    offset: Option<usize>,
+    length: Option<usize>,
+}
+
+#[async_trait]
+impl Respond for GetAttachmentMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let attachment = Attachment::get(&self.attachment_id).await?;
+        // Sent attachments are visible to anyone who can see the channel,
+        // emoji images to anyone in the emoji's space
+        if attachment.uploader_id != user.id {
+            if let Some(emoji_id) = &attachment.emoji_id {
+                let emoji = Emoji::get(emoji_id).await?;
+                if !user.in_space(&emoji.space_id).await? {
+                    return Err(Error::NotFound);
+                }
+            } else {
+                let Some(channel_id) = &attachment.channel_id else {
+                    return Err(Error::NotFound);
+                };
+                let channel = Channel::get(channel_id).await?;
+                check_can_view(&user, &channel).await?;
+            }
+        }
+        let length = self.length.unwrap_or(*MAX_CHUNK_SIZE).min(*MAX_CHUNK_SIZE);
+        let data = attachment.read(self.offset.unwrap_or(0), length).await?;
+        Ok(Response::GetAttachment(GetAttachmentResponse {
+            attachment: attachment.into(),
+            data,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAttachmentResponse {
+    attachment: MessageAttachment,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+}
@@ -9,18 +9,20 @@ use serde::{Deserialize, Serialize};
 use crate::errors::{Error, Result};
 use crate::methods::Response;
 use crate::services::database::users::User;
+use crate::services::dispatch::{self, Dispatch};
 use crate::services::encryption::generate_id;
 use crate::services::environment::JWT_SECRET;
+use crate::services::sessions::Session;
 use crate::services::socket::RpcClient;
 
 use super::Respond;
 
-#[derive(Deserialize)]
-struct UserJwt {
+#[derive(Deserialize, Serialize)]
+pub(crate) struct UserJwt {
     // TODO: Find the other properties
-    id: String,
-    issued_at: u128,
-    expires_at: u128,
+    pub(crate) id: String,
+    pub(crate) issued_at: u128,
+    pub(crate) expires_at: u128,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -28,6 +30,7 @@ struct UserJwt {
 pub struct IdentifyMethod {
     pub public_key: Vec<u8>,
     pub token: String,
+    pub device_name: Option<String>,
 }
 
 // Important: This only accepts a token and will not sign a token.
@@ -55,14 +58,30 @@ impl Respond for IdentifyMethod {
         if time > token_message.claims.expires_at {
             return Err(Error::InvalidToken);
         }
-        let mut client = clients.get_mut(&id).unwrap();
         let user = User::get(&token_message.claims.id).await;
         let user = if let Err(Error::NotFound) = user {
             User::create(token_message.claims.id).await?
         } else {
             user?
         };
-        client.user = Some(Arc::new(user));
+        let (session, previous) = {
+            let mut client = clients.get_mut(&id).unwrap();
+            let session = Session {
+                id: id.clone(),
+                user_id: user.id.clone(),
+                device_name: self.device_name.clone(),
+                ip: client.ip.clone(),
+                connected_at: client.connected_at,
+                last_heartbeat: time as i64,
+            };
+            client.user = Some(Arc::new(user));
+            let previous = client.session.replace(session.clone());
+            (session, previous)
+        };
+        if let Some(previous) = previous {
+            previous.delete().await?;
+        }
+        session.save().await?;
         Ok(Response::Identify(IdentifyResponse { success: true }))
     }
 }
@@ -84,8 +103,22 @@ impl Respond for HeartbeatMethod {
         clients: Arc<DashMap<String, RpcClient>>,
         id: String,
     ) -> Result<Response> {
-        let client = clients.get(&id).unwrap();
-        client.heartbeat_tx.send(()).await.unwrap();
+        let (heartbeat_tx, session) = {
+            let mut client = clients.get_mut(&id).unwrap();
+            if let Some(session) = client.session.as_mut() {
+                session.last_heartbeat = chrono::Utc::now().timestamp_millis();
+            }
+            (client.heartbeat_tx.clone(), client.session.clone())
+        };
+        heartbeat_tx.send(()).await.unwrap();
+        if let Some(session) = session {
+            if !session.refresh().await? {
+                if let Some((_, client)) = clients.remove(&id) {
+                    client.socket.close();
+                }
+                return Err(Error::NotAuthenticated);
+            }
+        }
         Ok(Response::Heartbeat(HeartbeatResponse { ack: true }))
     }
 }
@@ -126,6 +159,70 @@ pub struct GetIdResponse {
     pub request_ids: Vec<String>,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionsMethod {}
+
+#[async_trait]
+impl Respond for GetSessionsMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let sessions = Session::get_all(&user.id).await?;
+        Ok(Response::GetSessions(GetSessionsResponse {
+            sessions,
+            current_session_id: id,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionsResponse {
+    sessions: Vec<Session>,
+    current_session_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeSessionMethod {
+    session_id: String,
+}
+
+#[async_trait]
+impl Respond for RevokeSessionMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let session = Session::get(&user.id, &self.session_id).await?;
+        let Some(session) = session else {
+            return Err(Error::NotFound);
+        };
+        session.delete().await?;
+        // The session may be connected to another instance
+        dispatch::publish(Dispatch::RevokeSession {
+            user_id: user.id.clone(),
+            session_id: session.id.clone(),
+        })
+        .await?;
+        Ok(Response::RevokeSession(RevokeSessionResponse {
+            session_id: session.id,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeSessionResponse {
+    session_id: String,
+}
+
 pub fn check_authenticated(
     clients: Arc<DashMap<String, RpcClient>>,
     id: &str,
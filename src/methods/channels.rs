@@ -1,15 +1,39 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use ulid::Ulid;
 
 use crate::{
     errors::{Error, Result},
-    services::{database::channels::Channel, socket::RpcClient},
+    globals::MAX_SLOWMODE,
+    services::{
+        database::{
+            channels::{Channel, EntityType, PermissionOverride},
+            members::Member,
+            messages::{Message, SystemMessage},
+            read_states::{ChannelUnread, ReadState},
+            roles::Role,
+            spaces::Space,
+            users::{DirectMessagePrivacy, User},
+        },
+        dispatch::dispatch_event,
+        environment::MAX_GROUP_MEMBERS,
+        permissions::{Permission, PermissionSet},
+        socket::RpcClient,
+    },
 };
 
-use super::{authentication::check_authenticated, Respond, Response};
+use super::{
+    authentication::check_authenticated,
+    messages::{check_can_send, check_can_view, send_system_message},
+    ChannelAckedEvent, ChannelCreatedEvent, ChannelDeletedEvent, ChannelUpdatedEvent,
+    ChannelsReorderedEvent, Event, Respond, Response, ThreadCreatedEvent,
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -30,6 +54,13 @@ impl Respond for GetChannelMethod {
         let user = check_authenticated(clients, &id)?;
         let channel = Channel::get(&self.id).await?;
         match channel {
+            Channel::ThreadChannel { ref space_id, .. } => {
+                if self.space_id.is_some() && self.space_id.as_ref() != space_id.as_ref() {
+                    return Err(Error::NotFound);
+                }
+                check_can_view(&user, &channel).await?;
+                Ok(Response::GetChannel(GetChannelResponse { channel }))
+            }
             Channel::PrivateChannel { .. } | Channel::GroupChannel { .. } => {
                 if self.space_id.is_some() {
                     return Err(Error::NotFound);
@@ -42,7 +73,8 @@ impl Respond for GetChannelMethod {
             }
             Channel::InformationChannel { ref space_id, .. }
             | Channel::AnnouncementChannel { ref space_id, .. }
-            | Channel::ChatChannel { ref space_id, .. } => {
+            | Channel::ChatChannel { ref space_id, .. }
+            | Channel::CategoryChannel { ref space_id, .. } => {
                 if let Some(request_space_id) = &self.space_id {
                     if request_space_id != space_id {
                         return Err(Error::NotFound);
@@ -82,7 +114,18 @@ impl Respond for GetChannelsMethod {
     ) -> Result<Response> {
         let user = check_authenticated(clients, &id)?;
         let channels = user.get_channels().await?;
-        Ok(Response::GetChannels(GetChannelsResponse { channels }))
+        let mut unread = Vec::new();
+        for channel in &channels {
+            let roles = match channel.space_id() {
+                Some(space_id) => Member::get_or_default(&user.id, space_id).await?.roles,
+                None => Vec::new(),
+            };
+            unread.push(ReadState::get_unread(&user.id, channel.id(), &roles).await?);
+        }
+        Ok(Response::GetChannels(GetChannelsResponse {
+            channels,
+            unread,
+        }))
     }
 }
 
@@ -90,43 +133,570 @@ impl Respond for GetChannelsMethod {
 #[serde(rename_all = "camelCase")]
 pub struct GetChannelsResponse {
     channels: Vec<Channel>,
+    unread: Vec<ChannelUnread>,
+}
+
+// Marks the channel as read up to the message and syncs the user's other sessions
+pub(crate) async fn ack_channel(
+    user_id: &String,
+    channel_id: &String,
+    message_id: &String,
+) -> Result<()> {
+    ReadState::ack(user_id, channel_id, message_id).await?;
+    dispatch_event(
+        vec![user_id.clone()],
+        Event::ChannelAcked(ChannelAckedEvent {
+            channel_id: channel_id.clone(),
+            message_id: message_id.clone(),
+        }),
+    )
+    .await
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AckChannelMethod {
+    channel_id: String,
+    message_id: String,
+}
+
+#[async_trait]
+impl Respond for AckChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        let message = Message::get(&self.message_id).await?;
+        if &message.channel_id != channel.id() {
+            return Err(Error::NotFound);
+        }
+        ack_channel(&user.id, &self.channel_id, &self.message_id).await?;
+        Ok(Response::AckChannel(AckChannelResponse {}))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AckChannelResponse {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableChannelEncryptionMethod {
+    channel_id: String,
+}
+
+#[async_trait]
+impl Respond for EnableChannelEncryptionMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        // Only channels without a server-side audience can be end-to-end encrypted
+        if !matches!(
+            channel,
+            Channel::PrivateChannel { .. } | Channel::GroupChannel { .. }
+        ) {
+            return Err(Error::NotFound);
+        }
+        channel.enable_encryption().await?;
+        send_system_message(&channel, &user.id, SystemMessage::EncryptionEnabled).await?;
+        Ok(Response::EnableChannelEncryption(
+            EnableChannelEncryptionResponse {},
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableChannelEncryptionResponse {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateThreadMethod {
+    message_id: String,
+    name: String,
+}
+
+#[async_trait]
+impl Respond for CreateThreadMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let name = validate_name(&self.name)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_send(&user, &channel).await?;
+        let thread = channel
+            .create_thread(&message, name, user.id.clone())
+            .await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::ThreadCreated(ThreadCreatedEvent {
+                thread: thread.clone(),
+                channel_id: channel.id().clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::CreateThread(CreateThreadResponse { thread }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateThreadResponse {
+    thread: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetThreadsMethod {
+    channel_id: String,
+    archived: Option<bool>,
 }
+
+#[async_trait]
+impl Respond for GetThreadsMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        let threads = channel.get_threads(self.archived.unwrap_or(false)).await?;
+        let mut information = Vec::new();
+        for thread in threads {
+            let unread = match &thread {
+                Channel::ThreadChannel {
+                    id,
+                    participants,
+                    last_message_id: Some(last_message_id),
+                    ..
+                } if participants.contains(&user.id) => ReadState::get(&user.id, id)
+                    .await?
+                    .is_none_or(|r| &r.last_message_id < last_message_id),
+                _ => false,
+            };
+            information.push(ThreadInformation { thread, unread });
+        }
+        Ok(Response::GetThreads(GetThreadsResponse {
+            threads: information,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadInformation {
+    thread: Channel,
+    unread: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetThreadsResponse {
+    threads: Vec<ThreadInformation>,
+}
+
 // TODO: Partial structs
 
+fn validate_name(name: &str) -> Result<String> {
+    let trimmed = name.trim();
+    if trimmed.len() > 32 {
+        return Err(Error::NameTooLong);
+    }
+    if trimmed.is_empty() {
+        return Err(Error::NameEmpty);
+    }
+    Ok(trimmed.to_owned())
+}
+
+fn validate_description(description: &str) -> Result<String> {
+    let trimmed = description.trim();
+    if trimmed.len() > 1024 {
+        return Err(Error::DescriptionTooLong);
+    }
+    Ok(trimmed.to_owned())
+}
+
+fn validate_slowmode(slowmode_seconds: u32) -> Result<u32> {
+    if slowmode_seconds > *MAX_SLOWMODE {
+        return Err(Error::InvalidSlowmode);
+    }
+    Ok(slowmode_seconds)
+}
+
+async fn check_manage_channels(user: &User, space_id: &String) -> Result<()> {
+    if !user.in_space(space_id).await? {
+        return Err(Error::NotFound);
+    }
+    let member = Member::get_or_default(&user.id, space_id).await?;
+    let permissions = member.get_permissions().await?;
+    if !permissions.has_permission(Permission::ManageChannels) {
+        return Err(Error::MissingPermission {
+            permission: Permission::ManageChannels,
+        });
+    }
+    Ok(())
+}
+
+// Space channels need ManageChannels, group channels belong to their owner
+// and either side of a private channel may close it
+async fn check_can_manage(user: &User, channel: &Channel) -> Result<()> {
+    match channel {
+        Channel::PrivateChannel {
+            initiator_id,
+            target_id,
+            ..
+        } => {
+            if initiator_id != &user.id && target_id != &user.id {
+                return Err(Error::NotFound);
+            }
+        }
+        Channel::GroupChannel {
+            owner_id, members, ..
+        } => {
+            if !members.contains(&user.id) {
+                return Err(Error::NotFound);
+            }
+            if owner_id != &user.id {
+                return Err(Error::NotOwner);
+            }
+        }
+        Channel::InformationChannel { space_id, .. }
+        | Channel::AnnouncementChannel { space_id, .. }
+        | Channel::ChatChannel { space_id, .. }
+        | Channel::CategoryChannel { space_id, .. } => {
+            check_manage_channels(user, space_id).await?;
+        }
+        Channel::ThreadChannel { .. } => return Err(Error::NotFound),
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateChannelMethod {
     channel: ChannelInformation,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
 pub enum ChannelInformation {
     PrivateChannel {
         target_id: String,
         scope_id: Option<String>,
     },
     GroupChannel {
-        scope_id: String,
+        scope_id: Option<String>,
         name: String,
         description: Option<String>,
+        #[serde(default)]
+        members: Vec<String>,
     },
     InformationChannel {
         space_id: String,
         scope_id: Option<String>,
         name: String,
         description: Option<String>,
+        category_id: Option<String>,
     },
     AnnouncementChannel {
         space_id: String,
         scope_id: Option<String>,
         name: String,
         description: Option<String>,
+        category_id: Option<String>,
     },
     ChatChannel {
         space_id: String,
         scope_id: Option<String>,
         name: String,
         description: Option<String>,
+        category_id: Option<String>,
+        slowmode_seconds: Option<u32>,
     },
+    CategoryChannel {
+        space_id: String,
+        scope_id: Option<String>,
+        name: String,
+    },
+}
+
+impl ChannelInformation {
+    async fn into_channel(self, user: &User) -> Result<Channel> {
+        let id = Ulid::new().to_string();
+        let channel = match self {
+            ChannelInformation::PrivateChannel {
+                target_id,
+                scope_id,
+            } => {
+                if target_id == user.id {
+                    return Err(Error::NotFound);
+                }
+                let target = User::get(&target_id).await?;
+                if Channel::get_private(&user.id, &target_id).await?.is_some() {
+                    return Err(Error::AlreadyExists);
+                }
+                check_can_message(user, &target).await?;
+                Channel::new_private(
+                    user.id.clone(),
+                    target_id,
+                    scope_id.unwrap_or_else(|| "global".to_owned()),
+                )
+            }
+            ChannelInformation::GroupChannel {
+                scope_id,
+                name,
+                description,
+                members: invited,
+            } => {
+                let mut members = vec![user.id.clone()];
+                for member_id in invited {
+                    if !members.contains(&member_id) {
+                        members.push(member_id);
+                    }
+                }
+                if members.len() as u64 > *MAX_GROUP_MEMBERS {
+                    return Err(Error::ChannelFull);
+                }
+                for member_id in &members[1..] {
+                    check_can_add(user, member_id).await?;
+                }
+                Channel::GroupChannel {
+                    id,
+                    name: validate_name(&name)?,
+                    description: validate_description(&description.unwrap_or_default())?,
+                    owner_id: user.id.clone(),
+                    members,
+                    scope_id: scope_id.unwrap_or_else(|| "global".to_owned()),
+                    encrypted: false,
+                }
+            }
+            ChannelInformation::InformationChannel {
+                space_id,
+                scope_id,
+                name,
+                description,
+                category_id,
+            } => {
+                let scope_id = space_channel_scope(user, &space_id, scope_id).await?;
+                check_category(&space_id, category_id.as_ref()).await?;
+                Channel::InformationChannel {
+                    id,
+                    name: validate_name(&name)?,
+                    description: validate_description(&description.unwrap_or_default())?,
+                    space_id,
+                    scope_id,
+                    permissions: Vec::new(),
+                    // New channels follow their category
+                    permissions_synced: category_id.is_some(),
+                    category_id,
+                }
+            }
+            ChannelInformation::AnnouncementChannel {
+                space_id,
+                scope_id,
+                name,
+                description,
+                category_id,
+            } => {
+                let scope_id = space_channel_scope(user, &space_id, scope_id).await?;
+                check_category(&space_id, category_id.as_ref()).await?;
+                Channel::AnnouncementChannel {
+                    id,
+                    name: validate_name(&name)?,
+                    description: validate_description(&description.unwrap_or_default())?,
+                    space_id,
+                    scope_id,
+                    permissions: Vec::new(),
+                    announcer_role_id: None,
+                    // New channels follow their category
+                    permissions_synced: category_id.is_some(),
+                    category_id,
+                }
+            }
+            ChannelInformation::ChatChannel {
+                space_id,
+                scope_id,
+                name,
+                description,
+                category_id,
+                slowmode_seconds,
+            } => {
+                let scope_id = space_channel_scope(user, &space_id, scope_id).await?;
+                check_category(&space_id, category_id.as_ref()).await?;
+                Channel::ChatChannel {
+                    id,
+                    name: validate_name(&name)?,
+                    description: validate_description(&description.unwrap_or_default())?,
+                    space_id,
+                    scope_id,
+                    permissions: Vec::new(),
+                    // New channels follow their category
+                    permissions_synced: category_id.is_some(),
+                    category_id,
+                    slowmode_seconds: validate_slowmode(slowmode_seconds.unwrap_or(0))?,
+                }
+            }
+            ChannelInformation::CategoryChannel {
+                space_id,
+                scope_id,
+                name,
+            } => {
+                let scope_id = space_channel_scope(user, &space_id, scope_id).await?;
+                Channel::CategoryChannel {
+                    id,
+                    name: validate_name(&name)?,
+                    space_id,
+                    scope_id,
+                    permissions: Vec::new(),
+                }
+            }
+        };
+        Ok(channel)
+    }
+}
+
+// Blocks always apply, and the target's privacy setting decides whether
+// anyone other than their friends may start a conversation
+pub(crate) async fn check_can_message(user: &User, target: &User) -> Result<()> {
+    if user.is_blocked_with(target) {
+        return Err(Error::Blocked);
+    }
+    if target.is_friends_with(&user.id) {
+        return Ok(());
+    }
+    let allowed = match target.direct_message_privacy {
+        DirectMessagePrivacy::Everyone => true,
+        DirectMessagePrivacy::Friends => false,
+        DirectMessagePrivacy::SharedSpaces => user.shares_space_with(&target.id).await?,
+    };
+    if !allowed {
+        return Err(Error::DirectMessagesRestricted);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenDirectMessageMethod {
+    user_id: String,
+}
+
+#[async_trait]
+impl Respond for OpenDirectMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        if self.user_id == user.id {
+            return Err(Error::NotFound);
+        }
+        let target = User::get(&self.user_id).await?;
+        if user.is_blocked_with(&target) {
+            return Err(Error::Blocked);
+        }
+        if let Some(channel) = Channel::get_private(&user.id, &target.id).await? {
+            return Ok(Response::OpenDirectMessage(OpenDirectMessageResponse {
+                channel,
+            }));
+        }
+        check_can_message(&user, &target).await?;
+        let (channel, created) = Channel::open_private(&user.id, &target.id).await?;
+        if created {
+            dispatch_event(
+                vec![user.id.clone(), target.id.clone()],
+                Event::ChannelCreated(ChannelCreatedEvent {
+                    channel: channel.clone(),
+                }),
+            )
+            .await?;
+        }
+        Ok(Response::OpenDirectMessage(OpenDirectMessageResponse {
+            channel,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenDirectMessageResponse {
+    channel: Channel,
+}
+
+// Categories group channels of their own space and cannot be nested
+async fn check_category(space_id: &String, category_id: Option<&String>) -> Result<()> {
+    let Some(category_id) = category_id else {
+        return Ok(());
+    };
+    match Channel::get(category_id).await? {
+        Channel::CategoryChannel {
+            space_id: category_space_id,
+            ..
+        } if &category_space_id == space_id => Ok(()),
+        _ => Err(Error::NotFound),
+    }
+}
+
+// Channels in a space default to the space's scope
+async fn space_channel_scope(
+    user: &User,
+    space_id: &String,
+    scope_id: Option<String>,
+) -> Result<String> {
+    check_manage_channels(user, space_id).await?;
+    match scope_id {
+        Some(scope_id) => Ok(scope_id),
+        None => Ok(Space::get(space_id).await?.scope_id),
+    }
+}
+
+#[async_trait]
+impl Respond for CreateChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = self.channel.clone().into_channel(&user).await?;
+        channel.create().await?;
+        if let Some(space_id) = channel.space_id() {
+            Space::get(space_id)
+                .await?
+                .add_channel(channel.id())
+                .await?;
+        }
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::ChannelCreated(ChannelCreatedEvent {
+                channel: channel.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::CreateChannel(CreateChannelResponse { channel }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateChannelResponse {
+    channel: Channel,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -135,6 +705,42 @@ pub struct EditChannelMethod {
     channel_id: String,
     name: Option<String>,
     description: Option<String>,
+    slowmode_seconds: Option<u32>,
+}
+
+#[async_trait]
+impl Respond for EditChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_manage(&user, &channel).await?;
+        let name = self.name.as_deref().map(validate_name).transpose()?;
+        let description = self
+            .description
+            .as_deref()
+            .map(validate_description)
+            .transpose()?;
+        let slowmode_seconds = self.slowmode_seconds.map(validate_slowmode).transpose()?;
+        let channel = channel.update(name, description, slowmode_seconds).await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::ChannelUpdated(ChannelUpdatedEvent {
+                channel: channel.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::EditChannel(EditChannelResponse { channel }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditChannelResponse {
+    channel: Channel,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -143,9 +749,554 @@ pub struct DeleteChannelMethod {
     channel_id: String,
 }
 
+#[async_trait]
+impl Respond for DeleteChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_manage(&user, &channel).await?;
+        // Viewers have to be known before the channel is gone
+        let viewers = channel.get_viewers().await?;
+        let channel_ids = channel.delete().await?;
+        if let Some(space_id) = channel.space_id() {
+            Space::get(space_id)
+                .await?
+                .remove_channel(channel.id())
+                .await?;
+        }
+        dispatch_event(
+            viewers,
+            Event::ChannelDeleted(ChannelDeletedEvent {
+                channel_id: self.channel_id.clone(),
+                space_id: channel.space_id().cloned(),
+                thread_ids: channel_ids[1..].to_vec(),
+            }),
+        )
+        .await?;
+        Ok(Response::DeleteChannel(DeleteChannelResponse {
+            channel_id: self.channel_id.clone(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteChannelResponse {
+    channel_id: String,
+}
+
+// Overrides change who can see the channel: users who lost access are told
+// it is gone and users who gained access receive it in full
+async fn dispatch_audience_change(channel: &Channel, previous: Vec<String>) -> Result<()> {
+    let viewers = channel.get_viewers().await?;
+    let (kept, added): (Vec<_>, Vec<_>) =
+        viewers.iter().cloned().partition(|v| previous.contains(v));
+    let removed = previous
+        .into_iter()
+        .filter(|v| !viewers.contains(v))
+        .collect::<Vec<_>>();
+    dispatch_event(
+        kept,
+        Event::ChannelUpdated(ChannelUpdatedEvent {
+            channel: channel.clone(),
+        }),
+    )
+    .await?;
+    dispatch_event(
+        added,
+        Event::ChannelCreated(ChannelCreatedEvent {
+            channel: channel.clone(),
+        }),
+    )
+    .await?;
+    dispatch_event(
+        removed,
+        Event::ChannelDeleted(ChannelDeletedEvent {
+            channel_id: channel.id().clone(),
+            space_id: channel.space_id().cloned(),
+            thread_ids: Vec::new(),
+        }),
+    )
+    .await
+}
+
+async fn check_manage_permissions(user: &User, channel: &Channel) -> Result<()> {
+    check_can_view(user, channel).await?;
+    if !channel
+        .has_permission(&user.id, Permission::ManageChannelPermissions)
+        .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::ManageChannelPermissions,
+        });
+    }
+    Ok(())
+}
+
+// Channels synced to a category change audience along with it, so their
+// viewers are recorded before the category's overrides change
+async fn get_synced_viewers(channel: &Channel) -> Result<Vec<(Channel, Vec<String>)>> {
+    let mut synced = Vec::new();
+    for child in channel.get_synced_children().await? {
+        let viewers = child.get_viewers().await?;
+        synced.push((child, viewers));
+    }
+    Ok(synced)
+}
+
+async fn dispatch_synced_audience_changes(synced: Vec<(Channel, Vec<String>)>) -> Result<()> {
+    for (child, previous) in synced {
+        dispatch_audience_change(&child, previous).await?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChannelPermissionOverrideMethod {
+    channel_id: String,
+    id: String,
+    entity_type: EntityType,
+    allow: PermissionSet,
+    deny: PermissionSet,
+}
+
+#[async_trait]
+impl Respond for SetChannelPermissionOverrideMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_manage_permissions(&user, &channel).await?;
+        if !self.allow.is_channel_only()
+            || !self.deny.is_channel_only()
+            || self.allow.overlaps(&self.deny)
+        {
+            return Err(Error::InvalidPermissions);
+        }
+        let Some(space_id) = channel.space_id() else {
+            return Err(Error::NotFound);
+        };
+        match self.entity_type {
+            EntityType::Role => {
+                if &Role::get(&self.id).await?.space_id != space_id {
+                    return Err(Error::NotFound);
+                }
+            }
+            EntityType::Member => {
+                if !Space::get(space_id).await?.members.contains(&self.id) {
+                    return Err(Error::NotFound);
+                }
+            }
+        }
+        // Nobody can hand out permissions they do not have themselves
+        for permission in Permission::iter_channel() {
+            let granted =
+                self.allow.has_permission(permission) || self.deny.has_permission(permission);
+            if granted && !channel.has_permission(&user.id, permission).await? {
+                return Err(Error::MissingPermission { permission });
+            }
+        }
+        let previous = channel.get_viewers().await?;
+        let synced = get_synced_viewers(&channel).await?;
+        let channel = channel
+            .set_permission_override(PermissionOverride {
+                id: self.id.clone(),
+                allow: self.allow.clone(),
+                deny: self.deny.clone(),
+                entity_type: self.entity_type.clone(),
+            })
+            .await?;
+        dispatch_audience_change(&channel, previous).await?;
+        dispatch_synced_audience_changes(synced).await?;
+        Ok(Response::SetChannelPermissionOverride(
+            SetChannelPermissionOverrideResponse { channel },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChannelPermissionOverrideResponse {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteChannelPermissionOverrideMethod {
+    channel_id: String,
+    id: String,
+    entity_type: EntityType,
+}
+
+#[async_trait]
+impl Respond for DeleteChannelPermissionOverrideMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_manage_permissions(&user, &channel).await?;
+        let previous = channel.get_viewers().await?;
+        let synced = get_synced_viewers(&channel).await?;
+        let channel = channel
+            .delete_permission_override(&self.id, &self.entity_type)
+            .await?;
+        dispatch_audience_change(&channel, previous).await?;
+        dispatch_synced_audience_changes(synced).await?;
+        Ok(Response::DeleteChannelPermissionOverride(
+            DeleteChannelPermissionOverrideResponse { channel },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteChannelPermissionOverrideResponse {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPosition {
+    id: String,
+    category_id: Option<String>,
+    // Defaults to following the category when moved into one
+    sync_permissions: Option<bool>,
+}
+
+// Takes every channel of the space, in the order they should be shown
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderChannelsMethod {
+    space_id: String,
+    channels: Vec<ChannelPosition>,
+}
+
+#[async_trait]
+impl Respond for ReorderChannelsMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        check_manage_channels(&user, &self.space_id).await?;
+        let space = Space::get(&self.space_id).await?;
+        let channel_ids = self
+            .channels
+            .iter()
+            .map(|c| c.id.clone())
+            .collect::<Vec<_>>();
+        let unique = channel_ids.iter().collect::<HashSet<_>>();
+        if unique.len() != channel_ids.len()
+            || channel_ids.len() != space.channels.len()
+            || !space.channels.iter().all(|id| unique.contains(id))
+        {
+            return Err(Error::InvalidChannelOrder);
+        }
+        let channels = Channel::get_all_in_space(&self.space_id)
+            .await?
+            .into_iter()
+            .map(|c| (c.id().clone(), c))
+            .collect::<HashMap<_, _>>();
+        // Work out every move before changing anything
+        let mut moves = Vec::new();
+        for position in &self.channels {
+            let channel = channels
+                .get(&position.id)
+                .ok_or(Error::InvalidChannelOrder)?;
+            if let Some(category_id) = &position.category_id {
+                if !matches!(
+                    channels.get(category_id),
+                    Some(Channel::CategoryChannel { .. })
+                ) {
+                    return Err(Error::InvalidChannelOrder);
+                }
+            }
+            let (current, synced) = match channel {
+                Channel::InformationChannel {
+                    category_id,
+                    permissions_synced,
+                    ..
+                }
+                | Channel::AnnouncementChannel {
+                    category_id,
+                    permissions_synced,
+                    ..
+                }
+                | Channel::ChatChannel {
+                    category_id,
+                    permissions_synced,
+                    ..
+                } => (category_id.as_ref(), *permissions_synced),
+                _ if position.category_id.is_some() => return Err(Error::InvalidChannelOrder),
+                _ => continue,
+            };
+            let moved = current != position.category_id.as_ref();
+            let sync = position
+                .sync_permissions
+                .unwrap_or(if moved { true } else { synced });
+            if moved || sync != synced {
+                moves.push(
+                    channel
+                        .move_to_category(position.category_id.clone(), sync)
+                        .await?,
+                );
+            }
+        }
+        let mut previous = Vec::new();
+        for category_move in &moves {
+            previous.push(channels[&category_move.channel_id].get_viewers().await?);
+        }
+        let space = space.reorder_channels(channel_ids, &moves).await?;
+        for (category_move, previous) in moves.iter().zip(previous) {
+            let channel = Channel::get(&category_move.channel_id).await?;
+            dispatch_audience_change(&channel, previous).await?;
+        }
+        dispatch_event(
+            space.members.clone(),
+            Event::ChannelsReordered(ChannelsReorderedEvent {
+                space_id: space.id.clone(),
+                channel_ids: space.channels.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::ReorderChannels(ReorderChannelsResponse { space }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderChannelsResponse {
+    space: Space,
+}
+
+// Group members are kept in join order, which decides who inherits the
+// group when its owner leaves
+async fn get_group(user: &User, channel_id: &String) -> Result<(Channel, String, Vec<String>)> {
+    let channel = Channel::get(channel_id).await?;
+    match channel {
+        Channel::GroupChannel {
+            ref owner_id,
+            ref members,
+            ..
+        } if members.contains(&user.id) => {
+            let (owner_id, members) = (owner_id.clone(), members.clone());
+            Ok((channel, owner_id, members))
+        }
+        _ => Err(Error::NotFound),
+    }
+}
+
+// Adding someone to a group lets the adder message them, so the target's
+// direct message settings apply as well
+async fn check_can_add(user: &User, target_id: &String) -> Result<()> {
+    if target_id == &user.id {
+        return Err(Error::AlreadyExists);
+    }
+    let target = User::get(target_id).await?;
+    check_can_message(user, &target).await
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AddUserToChannelMethod {
     channel_id: String,
     user_id: String,
 }
+
+#[async_trait]
+impl Respond for AddUserToChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let (channel, _, members) = get_group(&user, &self.channel_id).await?;
+        check_can_add(&user, &self.user_id).await?;
+        let channel = channel.add_group_member(&self.user_id).await?;
+        dispatch_audience_change(&channel, members).await?;
+        send_system_message(
+            &channel,
+            &user.id,
+            SystemMessage::MemberAdded {
+                user_id: self.user_id.clone(),
+            },
+        )
+        .await?;
+        Ok(Response::AddUserToChannel(AddUserToChannelResponse {
+            channel,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddUserToChannelResponse {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveUserFromChannelMethod {
+    channel_id: String,
+    user_id: String,
+}
+
+#[async_trait]
+impl Respond for RemoveUserFromChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let (channel, owner_id, members) = get_group(&user, &self.channel_id).await?;
+        if owner_id != user.id {
+            return Err(Error::NotOwner);
+        }
+        let channel = channel.remove_group_member(&self.user_id).await?;
+        dispatch_audience_change(&channel, members).await?;
+        send_system_message(
+            &channel,
+            &user.id,
+            SystemMessage::MemberRemoved {
+                user_id: self.user_id.clone(),
+            },
+        )
+        .await?;
+        Ok(Response::RemoveUserFromChannel(
+            RemoveUserFromChannelResponse { channel },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveUserFromChannelResponse {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferChannelOwnershipMethod {
+    channel_id: String,
+    user_id: String,
+}
+
+#[async_trait]
+impl Respond for TransferChannelOwnershipMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let (channel, owner_id, members) = get_group(&user, &self.channel_id).await?;
+        if owner_id != user.id {
+            return Err(Error::NotOwner);
+        }
+        if self.user_id == user.id {
+            return Ok(Response::TransferChannelOwnership(
+                TransferChannelOwnershipResponse { channel },
+            ));
+        }
+        let channel = channel
+            .transfer_group_ownership(&user.id, &self.user_id)
+            .await?;
+        dispatch_event(
+            members,
+            Event::ChannelUpdated(ChannelUpdatedEvent {
+                channel: channel.clone(),
+            }),
+        )
+        .await?;
+        send_system_message(
+            &channel,
+            &user.id,
+            SystemMessage::OwnershipTransferred {
+                user_id: self.user_id.clone(),
+            },
+        )
+        .await?;
+        Ok(Response::TransferChannelOwnership(
+            TransferChannelOwnershipResponse { channel },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferChannelOwnershipResponse {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaveChannelMethod {
+    channel_id: String,
+}
+
+#[async_trait]
+impl Respond for LeaveChannelMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let (mut channel, owner_id, members) = get_group(&user, &self.channel_id).await?;
+        // The last member leaving takes the group with them
+        let Some(successor) = members.iter().find(|m| *m != &user.id) else {
+            let channel_ids = channel.delete().await?;
+            dispatch_event(
+                members,
+                Event::ChannelDeleted(ChannelDeletedEvent {
+                    channel_id: self.channel_id.clone(),
+                    space_id: None,
+                    thread_ids: channel_ids[1..].to_vec(),
+                }),
+            )
+            .await?;
+            return Ok(Response::LeaveChannel(LeaveChannelResponse {
+                channel_id: self.channel_id.clone(),
+            }));
+        };
+        if owner_id == user.id {
+            channel = channel
+                .transfer_group_ownership(&user.id, successor)
+                .await?;
+            send_system_message(
+                &channel,
+                &user.id,
+                SystemMessage::OwnershipTransferred {
+                    user_id: successor.clone(),
+                },
+            )
+            .await?;
+        }
+        let channel = channel.remove_group_member(&user.id).await?;
+        dispatch_audience_change(&channel, members).await?;
+        send_system_message(&channel, &user.id, SystemMessage::MemberLeft).await?;
+        Ok(Response::LeaveChannel(LeaveChannelResponse {
+            channel_id: self.channel_id.clone(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaveChannelResponse {
+    channel_id: String,
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    services::{
+        database::{
+            attachments::Attachment,
+            emojis::{validate_name, Emoji},
+            members::Member,
+            spaces::Space,
+            users::User,
+        },
+        dispatch::dispatch_event,
+        permissions::Permission,
+        socket::RpcClient,
+    },
+};
+
+use super::{
+    authentication::check_authenticated, EmojiCreatedEvent, EmojiDeletedEvent, EmojiUpdatedEvent,
+    Event, Respond, Response,
+};
+
+async fn check_manage_emoji(user: &User, space_id: &String) -> Result<()> {
+    if !user.in_space(space_id).await? {
+        return Err(Error::NotFound);
+    }
+    let member = Member::get_or_default(&user.id, space_id).await?;
+    let permissions = member.get_permissions().await?;
+    if !permissions.has_permission(Permission::ManageEmoji) {
+        return Err(Error::MissingPermission {
+            permission: Permission::ManageEmoji,
+        });
+    }
+    Ok(())
+}
+
+async fn dispatch_to_space(space_id: &String, event: Event) -> Result<()> {
+    let space = Space::get(space_id).await?;
+    dispatch_event(space.members, event).await
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateEmojiMethod {
+    space_id: String,
+    name: String,
+    file_id: String,
+}
+
+#[async_trait]
+impl Respond for CreateEmojiMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        check_manage_emoji(&user, &self.space_id).await?;
+        validate_name(&self.name)?;
+        let attachment = match Attachment::get(&self.file_id).await {
+            Ok(attachment) if attachment.uploader_id == user.id => attachment,
+            Ok(_) | Err(Error::NotFound) => return Err(Error::InvalidUpload),
+            Err(error) => return Err(error),
+        };
+        let emoji = Emoji::create(
+            &self.space_id,
+            self.name.clone(),
+            self.file_id.clone(),
+            user.id.clone(),
+        )
+        .await?;
+        // Claiming the file stops it from being sent or used twice
+        if let Err(error) = attachment.claim_for_emoji(&emoji.id).await {
+            emoji.delete().await?;
+            return Err(error);
+        }
+        dispatch_to_space(
+            &self.space_id,
+            Event::EmojiCreated(EmojiCreatedEvent {
+                emoji: emoji.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::CreateEmoji(CreateEmojiResponse { emoji }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateEmojiResponse {
+    emoji: Emoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditEmojiMethod {
+    id: String,
+    space_id: String,
+    name: Option<String>,
+}
+
+#[async_trait]
+impl Respond for EditEmojiMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let emoji = Emoji::get(&self.id).await?;
+        if emoji.space_id != self.space_id {
+            return Err(Error::NotFound);
+        }
+        check_manage_emoji(&user, &self.space_id).await?;
+        if let Some(name) = &self.name {
+            validate_name(name)?;
+        }
+        let emoji = emoji.update(self.name.clone()).await?;
+        dispatch_to_space(
+            &self.space_id,
+            Event::EmojiUpdated(EmojiUpdatedEvent {
+                emoji: emoji.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::EditEmoji(EditEmojiResponse { emoji }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditEmojiResponse {
+    emoji: Emoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteEmojiMethod {
+    id: String,
+    space_id: String,
+}
+
+#[async_trait]
+impl Respond for DeleteEmojiMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let emoji = Emoji::get(&self.id).await?;
+        if emoji.space_id != self.space_id {
+            return Err(Error::NotFound);
+        }
+        check_manage_emoji(&user, &self.space_id).await?;
+        emoji.delete().await?;
+        // Messages keep their references, which then render as plain text
+        match Attachment::get(&emoji.file_id).await {
+            Ok(attachment) if attachment.emoji_id.as_ref() == Some(&emoji.id) => {
+                attachment.delete().await?
+            }
+            Ok(_) | Err(Error::NotFound) => {}
+            Err(error) => return Err(error),
+        }
+        dispatch_to_space(
+            &self.space_id,
+            Event::EmojiDeleted(EmojiDeletedEvent {
+                id: emoji.id.clone(),
+                space_id: self.space_id.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::DeleteEmoji(DeleteEmojiResponse {
+            id: self.id.clone(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteEmojiResponse {
+    id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetEmojisMethod {
+    space_id: String,
+}
+
+#[async_trait]
+impl Respond for GetEmojisMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        if !user.in_space(&self.space_id).await? {
+            return Err(Error::NotFound);
+        }
+        let emojis = Emoji::get_all(&self.space_id).await?;
+        Ok(Response::GetEmojis(GetEmojisResponse { emojis }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetEmojisResponse {
+    emojis: Vec<Emoji>,
+}
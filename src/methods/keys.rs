@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    globals::{MAX_PREKEY_CLAIMS, PREKEY_CLAIM_WINDOW},
+    services::{
+        database::{
+            channels::Channel,
+            keys::{KeyBundle, Prekey, PrekeyBundle, SignedPrekey},
+            users::User,
+        },
+        redis::get_connection,
+        socket::RpcClient,
+    },
+};
+
+use super::{authentication::check_authenticated, channels::check_can_message, Respond, Response};
+
+// One-time prekeys are used up when claimed, so only users who already talk
+// to the target, or could start talking to them, may take any
+async fn check_can_claim(user: &User, target_id: &String) -> Result<()> {
+    if target_id == &user.id {
+        return Ok(());
+    }
+    let target = User::get(target_id).await?;
+    if user.is_blocked_with(&target) {
+        return Err(Error::Blocked);
+    }
+    if Channel::shares_direct(&user.id, target_id).await? {
+        return Ok(());
+    }
+    if !target.is_friends_with(&user.id) && !user.shares_space_with(target_id).await? {
+        return Err(Error::NotFound);
+    }
+    check_can_message(user, &target).await
+}
+
+// Counted per requester and target within a fixed window
+async fn limit_claims(user_id: &str, target_id: &str) -> Result<()> {
+    let key = format!("prekeys:{user_id}:{target_id}");
+    let mut redis = get_connection().await;
+    let (count,): (i64,) = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(&key)
+        .arg(0)
+        .arg("NX")
+        .arg("PX")
+        .arg(*PREKEY_CLAIM_WINDOW)
+        .ignore()
+        .incr(&key, 1)
+        .query_async(&mut redis)
+        .await?;
+    if count > *MAX_PREKEY_CLAIMS {
+        let remaining: i64 = redis.pttl(&key).await?;
+        return Err(Error::RateLimited {
+            retry_after: remaining.max(0),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishKeysMethod {
+    #[serde(with = "serde_bytes")]
+    identity_key: Vec<u8>,
+    signed_prekey: SignedPrekey,
+    #[serde(default)]
+    one_time_prekeys: Vec<Prekey>,
+}
+
+#[async_trait]
+impl Respond for PublishKeysMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let bundle = KeyBundle::publish(
+            &user.id,
+            self.identity_key.clone(),
+            self.signed_prekey.clone(),
+            self.one_time_prekeys.clone(),
+        )
+        .await?;
+        Ok(Response::PublishKeys(PublishKeysResponse {
+            remaining_prekeys: bundle.one_time_prekeys.len(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishKeysResponse {
+    remaining_prekeys: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPrekeysMethod {
+    one_time_prekeys: Vec<Prekey>,
+}
+
+#[async_trait]
+impl Respond for AddPrekeysMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        KeyBundle::add_prekeys(&user.id, self.one_time_prekeys.clone()).await?;
+        let bundle = KeyBundle::get(&user.id).await?;
+        Ok(Response::AddPrekeys(AddPrekeysResponse {
+            remaining_prekeys: bundle.one_time_prekeys.len(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPrekeysResponse {
+    remaining_prekeys: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetKeyBundleMethod {
+    user_id: String,
+}
+
+#[async_trait]
+impl Respond for GetKeyBundleMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        check_can_claim(&user, &self.user_id).await?;
+        limit_claims(&user.id, &self.user_id).await?;
+        let bundle = KeyBundle::claim(&self.user_id).await?;
+        Ok(Response::GetKeyBundle(GetKeyBundleResponse { bundle }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetKeyBundleResponse {
+    bundle: PrekeyBundle,
+}
@@ -1,20 +1,356 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, sync::Arc};
 
-use async_std::future;
 use async_trait::async_trait;
 use dashmap::DashMap;
-use rmp_serde::Serializer;
+use log::warn;
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     errors::{Error, Result},
+    globals::{
+        MAX_ATTACHMENTS, MAX_BULK_DELETE, MAX_CIPHERTEXT_SIZE, MAX_MESSAGE_LIFETIME,
+        MAX_NONCE_LENGTH, MAX_SCHEDULE_AHEAD, NONCE_WINDOW, TYPING_INTERVAL, TYPING_TIMEOUT,
+    },
     services::{
-        database::{channels::Channel, messages::Message},
-        socket::RpcClient,
+        database::{
+            attachments::{Attachment, MessageAttachment},
+            channels::Channel,
+            embeds::{Embed, EmbedKind},
+            emojis::{Emoji, MessageEmoji},
+            members::Member,
+            messages::{
+                BulkDeleteFilter, EncryptedContent, Mentions, Message, MessageDraft,
+                MessageReference, MessageRevision, MessageSearch, SystemMessage,
+            },
+            roles::Role,
+            scheduled_messages::ScheduledMessage,
+            spaces::Space,
+            users::User,
+        },
+        dispatch::dispatch_event,
+        embeds,
+        markdown::{self, ContentNode},
+        permissions::Permission,
+        redis::get_connection,
+        sessions::Session,
+        socket::{deserialize, serialize, RpcClient},
     },
 };
 
-use super::{Event, NewMessageEvent, Respond, Response, RpcApiEvent};
+use super::{
+    channels::ack_channel, Event, MentionedEvent, MessageDeletedEvent, MessageUpdatedEvent,
+    MessagesBulkDeletedEvent, NewMessageEvent, Respond, Response, TypingStartedEvent,
+};
+
+pub(crate) async fn check_can_view(user: &User, channel: &Channel) -> Result<()> {
+    if !user.in_channel(channel).await? {
+        return Err(Error::NotFound);
+    }
+    if channel.space_id().is_some()
+        && !channel
+            .has_permission(&user.id, Permission::ViewChannels)
+            .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::ViewChannels,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) async fn check_can_send(user: &User, channel: &Channel) -> Result<()> {
+    check_can_view(user, channel).await?;
+    match channel {
+        Channel::PrivateChannel { .. } | Channel::GroupChannel { .. } => Ok(()),
+        Channel::CategoryChannel { .. } => Err(Error::NotFound),
+        Channel::ChatChannel { .. } => {
+            if !channel
+                .has_permission(&user.id, Permission::SendMessages)
+                .await?
+            {
+                return Err(Error::MissingPermission {
+                    permission: Permission::SendMessages,
+                });
+            }
+            Ok(())
+        }
+        // Information channels are read-only for everyone but channel managers
+        Channel::InformationChannel { .. } => {
+            if !channel
+                .has_permission(&user.id, Permission::ManageChannels)
+                .await?
+            {
+                return Err(Error::MissingPermission {
+                    permission: Permission::ManageChannels,
+                });
+            }
+            Ok(())
+        }
+        Channel::ThreadChannel { .. } => {
+            if channel.space_id().is_some()
+                && !channel
+                    .has_permission(&user.id, Permission::SendMessages)
+                    .await?
+            {
+                return Err(Error::MissingPermission {
+                    permission: Permission::SendMessages,
+                });
+            }
+            Ok(())
+        }
+        Channel::AnnouncementChannel {
+            space_id,
+            announcer_role_id,
+            ..
+        } => {
+            if !channel
+                .has_permission(&user.id, Permission::SendMessages)
+                .await?
+            {
+                return Err(Error::MissingPermission {
+                    permission: Permission::SendMessages,
+                });
+            }
+            let member = Member::get_or_default(&user.id, space_id).await?;
+            let announcer = member.is_owner().await?
+                || member
+                    .get_permissions()
+                    .await?
+                    .has_permission(Permission::Administrator)
+                || announcer_role_id
+                    .as_ref()
+                    .is_some_and(|role_id| member.roles.contains(role_id));
+            if !announcer {
+                return Err(Error::MissingPermission {
+                    permission: Permission::SendMessages,
+                });
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn resolve_emojis(channel: &Channel, names: &[String]) -> Result<Vec<MessageEmoji>> {
+    match channel.space_id() {
+        Some(space_id) => Emoji::resolve(space_id, names).await,
+        None => Ok(Vec::new()),
+    }
+}
+
+async fn resolve_mentions(
+    user: &User,
+    channel: &Channel,
+    mut mentions: Mentions,
+) -> Result<Mentions> {
+    if mentions.is_mass_mention()
+        && channel.space_id().is_some()
+        && !channel
+            .has_permission(&user.id, Permission::MentionAll)
+            .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::MentionAll,
+        });
+    }
+    // Only keep mentions that point at something in this channel's scope
+    match channel.space_id() {
+        Some(space_id) => {
+            let space = Space::get(space_id).await?;
+            mentions.users.retain(|u| space.members.contains(u));
+            let mut roles = Vec::new();
+            for role_id in mentions.roles {
+                if let Ok(role) = Role::get(&role_id).await {
+                    if &role.space_id == space_id {
+                        roles.push(role_id);
+                    }
+                }
+            }
+            mentions.roles = roles;
+            let mut channels = Vec::new();
+            for channel_id in mentions.channels {
+                if let Ok(mentioned) = Channel::get(&channel_id).await {
+                    if mentioned.space_id() == Some(space_id) {
+                        channels.push(channel_id);
+                    }
+                }
+            }
+            mentions.channels = channels;
+        }
+        None => {
+            let viewers = channel.get_viewers().await?;
+            mentions.users.retain(|u| viewers.contains(u));
+            mentions.roles.clear();
+            mentions.channels.clear();
+        }
+    }
+    Ok(mentions)
+}
+
+// Mentioned users are notified even when they are not looking at the channel
+async fn notify_mentions(message: &Message, channel: &Channel, viewers: &[String]) -> Result<()> {
+    let mentions = &message.mentions;
+    let mut targets = mentions.users.clone();
+    if mentions.everyone {
+        targets.extend(viewers.iter().cloned());
+    } else if mentions.here {
+        for viewer in viewers {
+            if Session::is_online(viewer).await? {
+                targets.push(viewer.clone());
+            }
+        }
+    }
+    if let Some(space_id) = channel.space_id() {
+        if !mentions.roles.is_empty() {
+            let members = Member::get_with_roles(space_id, &mentions.roles).await?;
+            targets.extend(members.into_iter().map(|m| m.id));
+        }
+    }
+    targets.retain(|t| viewers.contains(t) && t != &message.author_id);
+    targets.sort();
+    targets.dedup();
+    dispatch_event(
+        targets,
+        Event::Mentioned(MentionedEvent {
+            message: message.clone(),
+            channel_id: message.channel_id.clone(),
+        }),
+    )
+    .await
+}
+
+async fn resolve_attachments(
+    user: &User,
+    channel: &Channel,
+    ids: &[String],
+    scheduled_id: Option<&String>,
+) -> Result<Vec<MessageAttachment>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    if ids.len() > *MAX_ATTACHMENTS {
+        return Err(Error::InvalidUpload);
+    }
+    if channel.space_id().is_some()
+        && !channel
+            .has_permission(&user.id, Permission::SendMultimediaMessages)
+            .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::SendMultimediaMessages,
+        });
+    }
+    let attachments = Attachment::get_unclaimed(ids, &user.id, scheduled_id).await?;
+    Ok(attachments.into_iter().map(|a| a.into()).collect())
+}
+
+pub(crate) async fn send_system_message(
+    channel: &Channel,
+    author_id: &str,
+    system: SystemMessage,
+) -> Result<Message> {
+    let message = Message::new_system(channel.id().clone(), author_id.to_owned(), system);
+    message.create().await?;
+    channel
+        .touch_thread(&message.author_id, &message.id)
+        .await?;
+    dispatch_event(
+        channel.get_viewers().await?,
+        Event::NewMessage(NewMessageEvent {
+            message: message.clone(),
+            channel_id: message.channel_id.clone(),
+            nonce: None,
+        }),
+    )
+    .await?;
+    Ok(message)
+}
+
+// References that do not point at anything in the channel's scope are
+// kept as plain text in the tree
+async fn resolve_content(
+    user: &User,
+    channel: &Channel,
+    content: &str,
+) -> Result<(Vec<ContentNode>, Vec<MessageEmoji>, Mentions)> {
+    let parsed = markdown::parse(content)?;
+    let emojis = resolve_emojis(channel, &parsed.emojis).await?;
+    let mentions = resolve_mentions(user, channel, parsed.mentions).await?;
+    let names = emojis.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+    let ast = markdown::retain_references(parsed.nodes, &mentions, &names);
+    Ok((ast, emojis, mentions))
+}
+
+// Links are only unfurled where the author may embed them
+async fn can_embed(user: &User, channel: &Channel) -> Result<bool> {
+    // The server cannot see the content of encrypted channels
+    if channel.is_encrypted().await? {
+        return Ok(false);
+    }
+    match channel.space_id() {
+        Some(_) => {
+            channel
+                .has_permission(&user.id, Permission::EmbedMessages)
+                .await
+        }
+        None => Ok(true),
+    }
+}
+
+async fn resolve_embeds(user: &User, channel: &Channel, embeds: &[Embed]) -> Result<Vec<Embed>> {
+    if embeds.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !user.bot || channel.is_encrypted().await? {
+        return Err(Error::InvalidEmbed);
+    }
+    if !can_embed(user, channel).await? {
+        return Err(Error::MissingPermission {
+            permission: Permission::EmbedMessages,
+        });
+    }
+    Embed::validate_rich(embeds.to_vec())
+}
+
+// Encrypted channels only accept ciphertext, wrapped for the channel's members
+async fn validate_encryption(
+    channel: &Channel,
+    content: &str,
+    encrypted: Option<&EncryptedContent>,
+) -> Result<()> {
+    if !channel.is_encrypted().await? {
+        return match encrypted {
+            Some(_) => Err(Error::NotEncrypted),
+            None => Ok(()),
+        };
+    }
+    let Some(encrypted) = encrypted else {
+        return Err(Error::EncryptionRequired);
+    };
+    if !content.is_empty() {
+        return Err(Error::EncryptionRequired);
+    }
+    if encrypted.ciphertext.is_empty() {
+        return Err(Error::MessageEmpty);
+    }
+    if encrypted.ciphertext.len() > *MAX_CIPHERTEXT_SIZE {
+        return Err(Error::MessageTooLong);
+    }
+    let viewers = channel.get_viewers().await?;
+    let mut recipients = HashSet::new();
+    for envelope in &encrypted.envelopes {
+        if envelope.key.is_empty()
+            || !viewers.contains(&envelope.recipient_id)
+            || !recipients.insert(&envelope.recipient_id)
+        {
+            return Err(Error::InvalidKeyBundle);
+        }
+    }
+    if recipients.is_empty() {
+        return Err(Error::InvalidKeyBundle);
+    }
+    Ok(())
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -33,8 +369,9 @@ impl Respond for GetMessagesMethod {
         clients: Arc<DashMap<String, RpcClient>>,
         id: String,
     ) -> Result<Response> {
-        super::authentication::check_authenticated(clients, &id)?;
+        let user = super::authentication::check_authenticated(clients, &id)?;
         let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
         let messages = channel
             .get_messages(
                 self.limit,
@@ -43,6 +380,14 @@ impl Respond for GetMessagesMethod {
                 self.after.clone(),
             )
             .await?;
+        // Opening the newest page of a thread marks it as read for participants
+        if let Channel::ThreadChannel { participants, .. } = &channel {
+            if self.latest.unwrap_or(false) && participants.contains(&user.id) {
+                if let Some(newest) = messages.first() {
+                    ack_channel(&user.id, &self.channel_id, &newest.id).await?;
+                }
+            }
+        }
         Ok(Response::GetMessages(GetMessagesResponse { messages }))
     }
 }
@@ -53,11 +398,275 @@ pub struct GetMessagesResponse {
     messages: Vec<Message>,
 }
 
+fn nonce_key(user_id: &str, nonce: &str) -> String {
+    format!("nonce:{user_id}:{nonce}")
+}
+
+// Retried sends carry the same nonce; the first response is remembered for
+// a while so that retries return it instead of sending the message again.
+// Returns None when the caller holds the nonce and should send the message.
+async fn claim_nonce(user_id: &str, nonce: &str) -> Result<Option<SendMessageResponse>> {
+    if nonce.is_empty() || nonce.len() > *MAX_NONCE_LENGTH {
+        return Err(Error::InvalidNonce);
+    }
+    let key = nonce_key(user_id, nonce);
+    let mut redis = get_connection().await;
+    // An empty value marks a send that is still in progress
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(Vec::<u8>::new())
+        .arg("NX")
+        .arg("PX")
+        .arg(*NONCE_WINDOW)
+        .query_async(&mut redis)
+        .await?;
+    if claimed.is_some() {
+        return Ok(None);
+    }
+    let response: Option<Vec<u8>> = redis.get(&key).await?;
+    match response {
+        Some(response) if !response.is_empty() => {
+            let response = deserialize(&response).map_err(|_| Error::InternalError)?;
+            Ok(Some(response))
+        }
+        _ => Err(Error::NoncePending),
+    }
+}
+
+async fn store_nonce(user_id: &str, nonce: &str, response: &SendMessageResponse) -> Result<()> {
+    let response = serialize(response).map_err(|_| Error::InternalError)?;
+    let mut redis = get_connection().await;
+    redis
+        .pset_ex::<_, _, ()>(nonce_key(user_id, nonce), response, *NONCE_WINDOW)
+        .await?;
+    Ok(())
+}
+
+async fn release_nonce(user_id: &str, nonce: &str) -> Result<()> {
+    let mut redis = get_connection().await;
+    redis.del::<_, ()>(nonce_key(user_id, nonce)).await?;
+    Ok(())
+}
+
+// Validates a draft and resolves everything the message references; the
+// uploads of a scheduled message are reserved for it
+async fn prepare_message(
+    user: &User,
+    draft: &MessageDraft,
+    scheduled_id: Option<&String>,
+) -> Result<(Channel, Message)> {
+    let content = markdown::validate(
+        &draft.content,
+        !draft.attachments.is_empty() || !draft.embeds.is_empty() || draft.encrypted.is_some(),
+    )?;
+    if draft
+        .expires_after
+        .is_some_and(|e| !(1..=*MAX_MESSAGE_LIFETIME).contains(&e))
+    {
+        return Err(Error::InvalidSchedule);
+    }
+    let channel = Channel::get(&draft.channel_id).await?;
+    check_can_send(user, &channel).await?;
+    validate_encryption(&channel, &content, draft.encrypted.as_ref()).await?;
+    let mut message = Message::new(draft.channel_id.clone(), user.id.clone(), content);
+    message.encrypted = draft.encrypted.clone();
+    message.attachments =
+        resolve_attachments(user, &channel, &draft.attachments, scheduled_id).await?;
+    message.embeds = resolve_embeds(user, &channel, &draft.embeds).await?;
+    if let Some(reply_id) = &draft.reply_to {
+        let replied = Message::get(reply_id).await?;
+        if replied.channel_id != draft.channel_id {
+            return Err(Error::NotFound);
+        }
+        message.reply_to = Some(MessageReference::from(&replied));
+    }
+    (message.ast, message.emojis, message.mentions) =
+        resolve_content(user, &channel, &message.content).await?;
+    message.expires_at = draft
+        .expires_after
+        .map(|expires_after| message.created_at + expires_after * 1000);
+    Ok((channel, message))
+}
+
+// Scheduled messages pass their entry, which holds the message ID chosen
+// when it was first claimed; they are held to the same slow mode as
+// messages sent right away
+pub(crate) async fn send_message(
+    user: &User,
+    draft: &MessageDraft,
+    scheduled: Option<&ScheduledMessage>,
+) -> Result<Message> {
+    let channel = Channel::get(&draft.channel_id).await?;
+    let cooldown = claim_slowmode(user, &channel).await?;
+    match create_message(user, draft, scheduled).await {
+        Ok(message) => Ok(message),
+        Err(error) => {
+            // Rejected messages do not count towards the cooldown
+            if cooldown {
+                release_slowmode(&user.id, channel.id()).await?;
+            }
+            Err(error)
+        }
+    }
+}
+
+async fn create_message(
+    user: &User,
+    draft: &MessageDraft,
+    scheduled: Option<&ScheduledMessage>,
+) -> Result<Message> {
+    let scheduled_id = scheduled.map(|s| &s.id);
+    let (channel, mut message) = prepare_message(user, draft, scheduled_id).await?;
+    if let Some(id) = scheduled.and_then(|s| s.message_id.clone()) {
+        message.id = id;
+    }
+    Attachment::claim(
+        &draft.attachments,
+        &user.id,
+        scheduled_id,
+        &message.id,
+        &message.channel_id,
+    )
+    .await?;
+    if let Err(error) = message.create().await {
+        Attachment::release(&message.id).await?;
+        return Err(error);
+    }
+    channel.touch_thread(&user.id, &message.id).await?;
+    ack_channel(&user.id, &message.channel_id, &message.id).await?;
+    let viewers = channel.get_viewers().await?;
+    dispatch_event(
+        viewers.clone(),
+        Event::NewMessage(NewMessageEvent {
+            message: message.clone(),
+            channel_id: message.channel_id.clone(),
+            nonce: draft.nonce.clone(),
+        }),
+    )
+    .await?;
+    notify_mentions(&message, &channel, &viewers).await?;
+    if can_embed(user, &channel).await? {
+        embeds::queue(&message);
+    }
+    Ok(message)
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SendMessageMethod {
     channel_id: String,
     content: String,
+    reply_to: Option<String>,
+    #[serde(default)]
+    attachments: Vec<String>,
+    #[serde(default)]
+    embeds: Vec<Embed>,
+    send_at: Option<i64>,
+    expires_after: Option<i64>,
+    encrypted: Option<EncryptedContent>,
+    nonce: Option<String>,
+}
+
+fn slowmode_key(user_id: &str, channel_id: &str) -> String {
+    format!("slowmode:{channel_id}:{user_id}")
+}
+
+async fn slowmode_applies(user: &User, channel: &Channel) -> Result<bool> {
+    Ok(channel.slowmode_seconds() != 0
+        && !channel
+            .has_permission(&user.id, Permission::ManageMessages)
+            .await?
+        && !channel
+            .has_permission(&user.id, Permission::ManageChannels)
+            .await?)
+}
+
+// Kept in Redis so the cooldown holds whichever instance the next message
+// lands on; returns whether a cooldown was started
+async fn claim_slowmode(user: &User, channel: &Channel) -> Result<bool> {
+    if !slowmode_applies(user, channel).await? {
+        return Ok(false);
+    }
+    let slowmode_seconds = channel.slowmode_seconds();
+    let key = slowmode_key(&user.id, channel.id());
+    let mut redis = get_connection().await;
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(Vec::<u8>::new())
+        .arg("NX")
+        .arg("EX")
+        .arg(slowmode_seconds)
+        .query_async(&mut redis)
+        .await?;
+    if claimed.is_some() {
+        return Ok(true);
+    }
+    let remaining: i64 = redis.pttl(&key).await?;
+    Err(Error::SlowmodeActive {
+        retry_after: remaining.max(0),
+    })
+}
+
+// Fails while a cooldown is running, without starting one
+async fn check_slowmode(user: &User, channel: &Channel) -> Result<()> {
+    if !slowmode_applies(user, channel).await? {
+        return Ok(());
+    }
+    let mut redis = get_connection().await;
+    let remaining: i64 = redis.pttl(slowmode_key(&user.id, channel.id())).await?;
+    if remaining > 0 {
+        return Err(Error::SlowmodeActive {
+            retry_after: remaining,
+        });
+    }
+    Ok(())
+}
+
+async fn release_slowmode(user_id: &str, channel_id: &str) -> Result<()> {
+    let mut redis = get_connection().await;
+    redis
+        .del::<_, ()>(slowmode_key(user_id, channel_id))
+        .await?;
+    Ok(())
+}
+
+impl SendMessageMethod {
+    async fn send(&self, user: &User) -> Result<SendMessageResponse> {
+        let draft = MessageDraft {
+            channel_id: self.channel_id.clone(),
+            content: self.content.clone(),
+            reply_to: self.reply_to.clone(),
+            attachments: self.attachments.clone(),
+            embeds: self.embeds.clone(),
+            expires_after: self.expires_after,
+            encrypted: self.encrypted.clone(),
+            nonce: self.nonce.clone(),
+        };
+        if let Some(send_at) = self.send_at {
+            let now = chrono::Utc::now().timestamp_millis();
+            if send_at <= now || send_at > now + *MAX_SCHEDULE_AHEAD * 1000 {
+                return Err(Error::InvalidSchedule);
+            }
+            // Checked now so the user finds out early, and again when sent
+            let (channel, _) = prepare_message(user, &draft, None).await?;
+            check_slowmode(user, &channel).await?;
+            let attachments = draft.attachments.clone();
+            let scheduled = ScheduledMessage::create(user.id.clone(), send_at, draft).await?;
+            if let Err(error) = Attachment::reserve(&attachments, &user.id, &scheduled.id).await {
+                ScheduledMessage::cancel(&scheduled.id, &user.id).await?;
+                return Err(error);
+            }
+            return Ok(SendMessageResponse {
+                message_id: scheduled.id,
+                scheduled: true,
+            });
+        }
+        let message = send_message(user, &draft, None).await?;
+        Ok(SendMessageResponse {
+            message_id: message.id,
+            scheduled: false,
+        })
+    }
 }
 
 #[async_trait]
@@ -67,44 +676,188 @@ impl Respond for SendMessageMethod {
         clients: Arc<DashMap<String, RpcClient>>,
         id: String,
     ) -> Result<Response> {
-        let user = super::authentication::check_authenticated(clients.clone(), &id)?;
-        let trimmed = self.content.trim();
-        if trimmed.len() > 4096 {
-            return Err(Error::MessageTooLong);
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let Some(nonce) = &self.nonce else {
+            return Ok(Response::SendMessage(self.send(&user).await?));
+        };
+        if let Some(response) = claim_nonce(&user.id, nonce).await? {
+            return Ok(Response::SendMessage(response));
         }
-        if trimmed.is_empty() {
-            return Err(Error::MessageEmpty);
-        }
-        let message =
-            Message::create(self.channel_id.clone(), user.id.clone(), trimmed.to_owned()).await?;
-        for x in clients.clone().iter_mut() {
-            // TODO: Check if user is in channel
-            if let Some(u) = &x.user {
-                println!("Sending message to {}", u.id);
-                let mut value_buffer = Vec::new();
-                let value = RpcApiEvent {
-                    event: Event::NewMessage(NewMessageEvent {
-                        message: message.clone(),
-                        channel_id: self.channel_id.clone(),
-                    }),
-                };
-                value
-                    .serialize(&mut Serializer::new(&mut value_buffer).with_struct_map())
-                    .unwrap();
-                println!("Serialized");
-                let y = x.socket.clone();
-                future::timeout(Duration::from_millis(5000), async move {
-                    y.send(async_tungstenite::tungstenite::Message::Binary(
-                        value_buffer,
-                    ))
-                    .await
-                })
-                .await
-                .unwrap_or(Ok(()))
-                .unwrap_or_else(|e| println!("{e:?}"));
+        match self.send(&user).await {
+            Ok(response) => {
+                // The message went out either way, so the client must not be
+                // told to retry; the pending claim keeps duplicates out until
+                // it expires
+                if let Err(e) = store_nonce(&user.id, nonce, &response).await {
+                    warn!("Failed to store nonce for {}: {e}", response.message_id);
+                }
+                Ok(Response::SendMessage(response))
             }
+            Err(error) => {
+                // A failed send may be retried with the same nonce
+                release_nonce(&user.id, nonce).await?;
+                Err(error)
+            }
+        }
+    }
+}
+
+// For scheduled messages, the ID is that of the pending entry
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageResponse {
+    message_id: String,
+    scheduled: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetScheduledMessagesMethod {
+    channel_id: Option<String>,
+}
+
+#[async_trait]
+impl Respond for GetScheduledMessagesMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let messages = ScheduledMessage::get_pending(&user.id, self.channel_id.as_ref()).await?;
+        Ok(Response::GetScheduledMessages(
+            GetScheduledMessagesResponse { messages },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetScheduledMessagesResponse {
+    messages: Vec<ScheduledMessage>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelScheduledMessageMethod {
+    id: String,
+}
+
+#[async_trait]
+impl Respond for CancelScheduledMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        ScheduledMessage::cancel(&self.id, &user.id).await?;
+        Attachment::release_reserved(&self.id).await?;
+        Ok(Response::CancelScheduledMessage(
+            CancelScheduledMessageResponse {
+                id: self.id.clone(),
+            },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelScheduledMessageResponse {
+    id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageMethod {
+    message_id: String,
+    content: String,
+    encrypted: Option<EncryptedContent>,
+}
+
+#[async_trait]
+impl Respond for EditMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        // Nobody may edit someone else's words, not even moderators
+        if message.author_id != user.id || message.system.is_some() {
+            return Err(Error::NotAuthor);
+        }
+        let content = markdown::validate(
+            &self.content,
+            !message.attachments.is_empty()
+                || message.embeds.iter().any(|e| e.kind == EmbedKind::Rich)
+                || self.encrypted.is_some(),
+        )?;
+        validate_encryption(&channel, &content, self.encrypted.as_ref()).await?;
+        let (ast, emojis, mentions) = resolve_content(&user, &channel, &content).await?;
+        let message = message
+            .edit(content, ast, emojis, mentions, self.encrypted.clone())
+            .await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::MessageUpdated(MessageUpdatedEvent {
+                message: message.clone(),
+                channel_id: message.channel_id.clone(),
+            }),
+        )
+        .await?;
+        if can_embed(&user, &channel).await? {
+            embeds::queue(&message);
         }
-        Ok(Response::SendMessage(SendMessageResponse {
+        Ok(Response::EditMessage(EditMessageResponse { message }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageResponse {
+    message: Message,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessageMethod {
+    message_id: String,
+}
+
+#[async_trait]
+impl Respond for DeleteMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        if message.author_id != user.id
+            && !channel
+                .has_permission(&user.id, Permission::ManageMessages)
+                .await?
+        {
+            return Err(Error::MissingPermission {
+                permission: Permission::ManageMessages,
+            });
+        }
+        message.delete().await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::MessageDeleted(MessageDeletedEvent {
+                message_id: message.id.clone(),
+                channel_id: message.channel_id.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::DeleteMessage(DeleteMessageResponse {
             message_id: message.id,
         }))
     }
@@ -112,6 +865,297 @@ impl Respond for SendMessageMethod {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct SendMessageResponse {
+pub struct DeleteMessageResponse {
     message_id: String,
 }
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetMessageRevisionsMethod {
+    message_id: String,
+}
+
+#[async_trait]
+impl Respond for GetMessageRevisionsMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        // Deleted messages only leave their revisions behind, which are
+        // visible to moderators alone
+        let (author_id, channel_id, revisions) = match Message::get(&self.message_id).await {
+            Ok(message) => (
+                Some(message.author_id.clone()),
+                message.channel_id.clone(),
+                message.get_revisions().await?,
+            ),
+            Err(Error::NotFound) => {
+                let revisions = MessageRevision::get_all(&self.message_id).await?;
+                match revisions.last() {
+                    Some(last) if last.deleted => (None, last.channel_id.clone(), revisions),
+                    _ => return Err(Error::NotFound),
+                }
+            }
+            Err(error) => return Err(error),
+        };
+        let channel = Channel::get(&channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        if author_id.as_ref() != Some(&user.id)
+            && !channel
+                .has_permission(&user.id, Permission::ManageMessages)
+                .await?
+        {
+            return Err(Error::MissingPermission {
+                permission: Permission::ManageMessages,
+            });
+        }
+        Ok(Response::GetMessageRevisions(GetMessageRevisionsResponse {
+            revisions,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetMessageRevisionsResponse {
+    revisions: Vec<MessageRevision>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTypingMethod {
+    channel_id: String,
+}
+
+#[async_trait]
+impl Respond for StartTypingMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_send(&user, &channel).await?;
+        // Typing is ephemeral and only lives in redis; repeated calls within
+        // the interval are accepted but not broadcast again
+        let mut redis = get_connection().await;
+        let first: Option<String> = redis::cmd("SET")
+            .arg(format!("typing:{}:{}", self.channel_id, user.id))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(*TYPING_INTERVAL)
+            .query_async(&mut redis)
+            .await?;
+        if first.is_some() {
+            let expires_at = chrono::Utc::now().timestamp_millis() + *TYPING_TIMEOUT as i64;
+            let mut viewers = channel.get_viewers().await?;
+            viewers.retain(|v| v != &user.id);
+            dispatch_event(
+                viewers,
+                Event::TypingStarted(TypingStartedEvent {
+                    channel_id: self.channel_id.clone(),
+                    user_id: user.id.clone(),
+                    expires_at,
+                }),
+            )
+            .await?;
+        }
+        Ok(Response::StartTyping(StartTypingResponse {}))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTypingResponse {}
+
+// Every channel the user may read, narrowed to one space if requested
+async fn get_searchable_channels(user: &User, space_id: Option<&String>) -> Result<Vec<String>> {
+    let mut channel_ids = Vec::new();
+    let space_ids = match space_id {
+        Some(space_id) => {
+            if !user.in_space(space_id).await? {
+                return Err(Error::NotFound);
+            }
+            vec![space_id.clone()]
+        }
+        None => {
+            // Encrypted content cannot be searched by the server
+            for channel in Channel::get_direct(&user.id).await? {
+                if !channel.is_encrypted().await? {
+                    channel_ids.push(channel.id().clone());
+                }
+            }
+            user.get_spaces().await?.into_iter().map(|s| s.id).collect()
+        }
+    };
+    for space_id in space_ids {
+        let member = Member::get_or_default(&user.id, &space_id).await?;
+        let channels = Channel::get_all_in_space(&space_id).await?;
+        let mut visible = Vec::new();
+        for channel in &channels {
+            if !matches!(channel, Channel::ThreadChannel { .. })
+                && member
+                    .get_permission_in_channel(channel, Permission::ViewChannels)
+                    .await?
+            {
+                visible.push(channel.id().clone());
+            }
+        }
+        // Threads are visible wherever their parent is
+        for channel in &channels {
+            if let Channel::ThreadChannel { id, parent_id, .. } = channel {
+                if visible.contains(parent_id) {
+                    visible.push(id.clone());
+                }
+            }
+        }
+        channel_ids.extend(visible);
+    }
+    Ok(channel_ids)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMessagesMethod {
+    query: String,
+    channel_id: Option<String>,
+    space_id: Option<String>,
+    author_id: Option<String>,
+    mentions: Option<String>,
+    has_attachment: Option<bool>,
+    since: Option<i64>,
+    until: Option<i64>,
+    before: Option<String>,
+    limit: Option<i64>,
+}
+
+#[async_trait]
+impl Respond for SearchMessagesMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let text = self.query.trim();
+        if text.is_empty() {
+            return Err(Error::MessageEmpty);
+        }
+        let channel_ids = match &self.channel_id {
+            Some(channel_id) => {
+                let channel = Channel::get(channel_id).await?;
+                check_can_view(&user, &channel).await?;
+                if self
+                    .space_id
+                    .as_ref()
+                    .is_some_and(|s| channel.space_id() != Some(s))
+                {
+                    return Err(Error::NotFound);
+                }
+                if channel.is_encrypted().await? {
+                    return Err(Error::EncryptionRequired);
+                }
+                vec![channel_id.clone()]
+            }
+            None => get_searchable_channels(&user, self.space_id.as_ref()).await?,
+        };
+        let search = MessageSearch {
+            text: text.to_owned(),
+            channel_ids,
+            author_id: self.author_id.clone(),
+            mentions: self.mentions.clone(),
+            has_attachment: self.has_attachment,
+            since: self.since,
+            until: self.until,
+            before: self.before.clone(),
+            limit: self.limit.unwrap_or(25).clamp(1, 100),
+        };
+        let messages = Message::search(&search).await?;
+        Ok(Response::SearchMessages(SearchMessagesResponse {
+            messages,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMessagesResponse {
+    messages: Vec<Message>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkDeleteMessagesMethod {
+    channel_id: String,
+    message_ids: Option<Vec<String>>,
+    author_id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    last: Option<i64>,
+}
+
+#[async_trait]
+impl Respond for BulkDeleteMessagesMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = super::authentication::check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        if !channel
+            .has_permission(&user.id, Permission::ManageMessages)
+            .await?
+        {
+            return Err(Error::MissingPermission {
+                permission: Permission::ManageMessages,
+            });
+        }
+        // Refuse to wipe a whole channel by accident
+        let filtered = self.message_ids.as_ref().is_some_and(|ids| !ids.is_empty())
+            || self.author_id.is_some()
+            || self.since.is_some()
+            || self.until.is_some()
+            || self.last.is_some();
+        if !filtered
+            || self
+                .message_ids
+                .as_ref()
+                .is_some_and(|ids| ids.len() as i64 > *MAX_BULK_DELETE)
+        {
+            return Err(Error::InvalidFilter);
+        }
+        let filter = BulkDeleteFilter {
+            message_ids: self.message_ids.clone(),
+            author_id: self.author_id.clone(),
+            since: self.since,
+            until: self.until,
+            last: self.last,
+        };
+        let message_ids = Message::bulk_delete(&self.channel_id, &filter).await?;
+        if !message_ids.is_empty() {
+            dispatch_event(
+                channel.get_viewers().await?,
+                Event::MessagesBulkDeleted(MessagesBulkDeletedEvent {
+                    message_ids: message_ids.clone(),
+                    channel_id: self.channel_id.clone(),
+                }),
+            )
+            .await?;
+        }
+        Ok(Response::BulkDeleteMessages(BulkDeleteMessagesResponse {
+            message_ids,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkDeleteMessagesResponse {
+    message_ids: Vec<String>,
+}
@@ -6,20 +6,64 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     errors::{Error, Result},
-    services::{database::messages::Message, socket::RpcClient},
+    services::{
+        database::{channels::Channel, emojis::Emoji, messages::Message, reactions::ReactionEmoji},
+        socket::RpcClient,
+    },
 };
 
 use self::{
+    attachments::{
+        CompleteUploadMethod, CompleteUploadResponse, CreateUploadMethod, CreateUploadResponse,
+        GetAttachmentMethod, GetAttachmentResponse, UploadChunkMethod, UploadChunkResponse,
+    },
     authentication::{
-        GetIdMethod, GetIdResponse, HeartbeatMethod, HeartbeatResponse, IdentifyMethod,
-        IdentifyResponse,
+        GetIdMethod, GetIdResponse, GetSessionsMethod, GetSessionsResponse, HeartbeatMethod,
+        HeartbeatResponse, IdentifyMethod, IdentifyResponse, RevokeSessionMethod,
+        RevokeSessionResponse,
+    },
+    channels::{
+        AckChannelMethod, AckChannelResponse, AddUserToChannelMethod, AddUserToChannelResponse,
+        CreateChannelMethod, CreateChannelResponse, CreateThreadMethod, CreateThreadResponse,
+        DeleteChannelMethod, DeleteChannelPermissionOverrideMethod,
+        DeleteChannelPermissionOverrideResponse, DeleteChannelResponse, EditChannelMethod,
+        EditChannelResponse, EnableChannelEncryptionMethod, EnableChannelEncryptionResponse,
+        GetChannelMethod, GetChannelResponse, GetChannelsMethod, GetChannelsResponse,
+        GetThreadsMethod, GetThreadsResponse, LeaveChannelMethod, LeaveChannelResponse,
+        OpenDirectMessageMethod, OpenDirectMessageResponse, RemoveUserFromChannelMethod,
+        RemoveUserFromChannelResponse, ReorderChannelsMethod, ReorderChannelsResponse,
+        SetChannelPermissionOverrideMethod, SetChannelPermissionOverrideResponse,
+        TransferChannelOwnershipMethod, TransferChannelOwnershipResponse,
+    },
+    emojis::{
+        CreateEmojiMethod, CreateEmojiResponse, DeleteEmojiMethod, DeleteEmojiResponse,
+        EditEmojiMethod, EditEmojiResponse, GetEmojisMethod, GetEmojisResponse,
     },
-    channels::{GetChannelMethod, GetChannelResponse, GetChannelsMethod, GetChannelsResponse},
     invites::{
         CreateInviteMethod, CreateInviteResponse, DeleteInviteMethod, DeleteInviteResponse,
         GetInviteMethod, GetInviteResponse, GetInvitesMethod, GetInvitesResponse,
     },
-    messages::{GetMessagesMethod, GetMessagesResponse, SendMessageMethod, SendMessageResponse},
+    keys::{
+        AddPrekeysMethod, AddPrekeysResponse, GetKeyBundleMethod, GetKeyBundleResponse,
+        PublishKeysMethod, PublishKeysResponse,
+    },
+    messages::{
+        BulkDeleteMessagesMethod, BulkDeleteMessagesResponse, CancelScheduledMessageMethod,
+        CancelScheduledMessageResponse, DeleteMessageMethod, DeleteMessageResponse,
+        EditMessageMethod, EditMessageResponse, GetMessageRevisionsMethod,
+        GetMessageRevisionsResponse, GetMessagesMethod, GetMessagesResponse,
+        GetScheduledMessagesMethod, GetScheduledMessagesResponse, SearchMessagesMethod,
+        SearchMessagesResponse, SendMessageMethod, SendMessageResponse, StartTypingMethod,
+        StartTypingResponse,
+    },
+    pins::{
+        GetPinnedMessagesMethod, GetPinnedMessagesResponse, PinMessageMethod, PinMessageResponse,
+        UnpinMessageMethod, UnpinMessageResponse,
+    },
+    reactions::{
+        AddReactionMethod, AddReactionResponse, GetReactionsMethod, GetReactionsResponse,
+        RemoveReactionMethod, RemoveReactionResponse,
+    },
     roles::{
         CreateRoleMethod, CreateRoleResponse, DeleteRoleMethod, DeleteRoleResponse, EditRoleMethod,
         EditRoleResponse,
@@ -29,17 +73,23 @@ use self::{
         EditSpaceMethod, EditSpaceResponse, GetSpaceMethod, GetSpaceResponse, GetSpacesResponse,
         JoinSpaceResponse, LeaveSpaceResponse,
     },
+    users::{SetDirectMessagePrivacyMethod, SetDirectMessagePrivacyResponse},
     webrtc::{
         EndCallMethod, EndCallResponse, JoinCallMethod, JoinCallResponse, LeaveCallMethod,
         LeaveCallResponse, StartCallMethod, StartCallResponse,
     },
 };
 
+pub mod attachments;
 pub mod authentication;
 pub mod channels;
+pub mod emojis;
 pub mod events;
 pub mod invites;
+pub mod keys;
 pub mod messages;
+pub mod pins;
+pub mod reactions;
 pub mod roles;
 pub mod spaces;
 pub mod users;
@@ -52,6 +102,8 @@ pub enum Method {
     Identify(IdentifyMethod) = 1,
     Heartbeat(HeartbeatMethod) = 2,
     GetId(GetIdMethod) = 3,
+    GetSessions(GetSessionsMethod) = 4,
+    RevokeSession(RevokeSessionMethod) = 5,
 
     // WebRTC: 10-19
     StartCall(StartCallMethod) = 10,
@@ -61,22 +113,37 @@ pub enum Method {
 
     GetMessages(GetMessagesMethod) = 20,
     SendMessage(SendMessageMethod) = 22,
+    EditMessage(EditMessageMethod) = 23,
+    DeleteMessage(DeleteMessageMethod) = 24,
+    GetMessageRevisions(GetMessageRevisionsMethod) = 25,
+    AddReaction(AddReactionMethod) = 26,
+    RemoveReaction(RemoveReactionMethod) = 27,
+    GetReactions(GetReactionsMethod) = 28,
+    StartTyping(StartTypingMethod) = 29,
 
     GetChannel(GetChannelMethod) = 30,
     GetChannels(GetChannelsMethod) = 31,
-    // CreateChannel(CreateChannelMethod) = 32,
-    // EditChannel(EditChannelMethod) = 33,
-    // DeleteChannel(DeleteChannelMethod) = 34,
+    CreateChannel(CreateChannelMethod) = 32,
+    EditChannel(EditChannelMethod) = 33,
+    DeleteChannel(DeleteChannelMethod) = 34,
+    CreateThread(CreateThreadMethod) = 35,
+    GetThreads(GetThreadsMethod) = 36,
+    AckChannel(AckChannelMethod) = 37,
+    SetChannelPermissionOverride(SetChannelPermissionOverrideMethod) = 38,
+    DeleteChannelPermissionOverride(DeleteChannelPermissionOverrideMethod) = 39,
+
     GetSpace(GetSpaceMethod) = 40,
     CreateSpace(CreateSpaceMethod) = 41,
     EditSpace(EditSpaceMethod) = 42,
     DeleteSpace(DeleteSpaceMethod) = 43,
+    ReorderChannels(ReorderChannelsMethod) = 47,
 
     // AddFriend(AddFriendMethod) = 50,
     // RemoveFriend(RemoveFriendMethod) = 51,
     // GetFriends(GetFriendsMethod) = 52,
     // GetFriendRequests(GetFriendRequestsMethod) = 53,
     // AcknowledgeFriendRequest(AcknowledgeFriendRequestMethod) = 55,
+    SetDirectMessagePrivacy(SetDirectMessagePrivacyMethod) = 56,
     CreateInvite(CreateInviteMethod) = 60,
     DeleteInvite(DeleteInviteMethod) = 61,
     GetInvite(GetInviteMethod) = 62,
@@ -86,6 +153,30 @@ pub enum Method {
     EditRole(EditRoleMethod) = 71,
     DeleteRole(DeleteRoleMethod) = 72,
     // GetRoles(GetRolesMethod) = 73,
+    CreateEmoji(CreateEmojiMethod) = 80,
+    EditEmoji(EditEmojiMethod) = 81,
+    DeleteEmoji(DeleteEmojiMethod) = 82,
+    GetEmojis(GetEmojisMethod) = 83,
+    CreateUpload(CreateUploadMethod) = 90,
+    UploadChunk(UploadChunkMethod) = 91,
+    CompleteUpload(CompleteUploadMethod) = 92,
+    GetAttachment(GetAttachmentMethod) = 93,
+    PinMessage(PinMessageMethod) = 100,
+    UnpinMessage(UnpinMessageMethod) = 101,
+    GetPinnedMessages(GetPinnedMessagesMethod) = 102,
+    SearchMessages(SearchMessagesMethod) = 103,
+    BulkDeleteMessages(BulkDeleteMessagesMethod) = 104,
+    GetScheduledMessages(GetScheduledMessagesMethod) = 105,
+    CancelScheduledMessage(CancelScheduledMessageMethod) = 106,
+    PublishKeys(PublishKeysMethod) = 110,
+    AddPrekeys(AddPrekeysMethod) = 111,
+    GetKeyBundle(GetKeyBundleMethod) = 112,
+    EnableChannelEncryption(EnableChannelEncryptionMethod) = 113,
+    AddUserToChannel(AddUserToChannelMethod) = 120,
+    RemoveUserFromChannel(RemoveUserFromChannelMethod) = 121,
+    TransferChannelOwnership(TransferChannelOwnershipMethod) = 122,
+    LeaveChannel(LeaveChannelMethod) = 123,
+    OpenDirectMessage(OpenDirectMessageMethod) = 124,
 }
 
 #[async_trait]
@@ -102,26 +193,42 @@ pub fn get_respond(m: Method) -> Box<dyn Respond + Send + Sync> {
         Method::Identify(m) => Box::new(m),
         Method::Heartbeat(m) => Box::new(m),
         Method::GetId(m) => Box::new(m),
+        Method::GetSessions(m) => Box::new(m),
+        Method::RevokeSession(m) => Box::new(m),
         Method::StartCall(m) => Box::new(m),
         Method::JoinCall(m) => Box::new(m),
         Method::LeaveCall(m) => Box::new(m),
         Method::EndCall(m) => Box::new(m),
         Method::GetMessages(m) => Box::new(m),
         Method::SendMessage(m) => Box::new(m),
+        Method::EditMessage(m) => Box::new(m),
+        Method::DeleteMessage(m) => Box::new(m),
+        Method::GetMessageRevisions(m) => Box::new(m),
+        Method::AddReaction(m) => Box::new(m),
+        Method::RemoveReaction(m) => Box::new(m),
+        Method::GetReactions(m) => Box::new(m),
+        Method::StartTyping(m) => Box::new(m),
         Method::GetChannel(m) => Box::new(m),
         Method::GetChannels(m) => Box::new(m),
-        // Method::CreateChannel(m) => m,
-        // Method::EditChannel(m) => m,
-        // Method::DeleteChannel(m) => m,
+        Method::CreateChannel(m) => Box::new(m),
+        Method::EditChannel(m) => Box::new(m),
+        Method::DeleteChannel(m) => Box::new(m),
+        Method::CreateThread(m) => Box::new(m),
+        Method::GetThreads(m) => Box::new(m),
+        Method::AckChannel(m) => Box::new(m),
+        Method::SetChannelPermissionOverride(m) => Box::new(m),
+        Method::DeleteChannelPermissionOverride(m) => Box::new(m),
         Method::GetSpace(m) => Box::new(m),
         Method::CreateSpace(m) => Box::new(m),
         Method::EditSpace(m) => Box::new(m),
         Method::DeleteSpace(m) => Box::new(m),
+        Method::ReorderChannels(m) => Box::new(m),
         // Method::AddFriend(m) => m,
         // Method::RemoveFriend(m) => m,
         // Method::GetFriends(m) => m,
         // Method::GetFriendRequests(m) => m,
         // Method::AcknowledgeFriendRequest(m) => m,
+        Method::SetDirectMessagePrivacy(m) => Box::new(m),
         Method::CreateInvite(m) => Box::new(m),
         Method::CreateRole(m) => Box::new(m),
         Method::EditRole(m) => Box::new(m),
@@ -129,6 +236,30 @@ pub fn get_respond(m: Method) -> Box<dyn Respond + Send + Sync> {
         Method::DeleteInvite(m) => Box::new(m),
         Method::GetInvite(m) => Box::new(m),
         Method::GetInvites(m) => Box::new(m),
+        Method::CreateEmoji(m) => Box::new(m),
+        Method::EditEmoji(m) => Box::new(m),
+        Method::DeleteEmoji(m) => Box::new(m),
+        Method::GetEmojis(m) => Box::new(m),
+        Method::CreateUpload(m) => Box::new(m),
+        Method::UploadChunk(m) => Box::new(m),
+        Method::CompleteUpload(m) => Box::new(m),
+        Method::GetAttachment(m) => Box::new(m),
+        Method::PinMessage(m) => Box::new(m),
+        Method::UnpinMessage(m) => Box::new(m),
+        Method::GetPinnedMessages(m) => Box::new(m),
+        Method::SearchMessages(m) => Box::new(m),
+        Method::BulkDeleteMessages(m) => Box::new(m),
+        Method::GetScheduledMessages(m) => Box::new(m),
+        Method::CancelScheduledMessage(m) => Box::new(m),
+        Method::PublishKeys(m) => Box::new(m),
+        Method::AddPrekeys(m) => Box::new(m),
+        Method::GetKeyBundle(m) => Box::new(m),
+        Method::EnableChannelEncryption(m) => Box::new(m),
+        Method::AddUserToChannel(m) => Box::new(m),
+        Method::RemoveUserFromChannel(m) => Box::new(m),
+        Method::TransferChannelOwnership(m) => Box::new(m),
+        Method::LeaveChannel(m) => Box::new(m),
+        Method::OpenDirectMessage(m) => Box::new(m),
     }
 }
 
@@ -175,6 +306,8 @@ pub enum Response {
     Identify(IdentifyResponse) = 1,
     Heartbeat(HeartbeatResponse) = 2,
     GetId(GetIdResponse) = 3,
+    GetSessions(GetSessionsResponse) = 4,
+    RevokeSession(RevokeSessionResponse) = 5,
 
     // WebRTC: 10-19
     StartCall(StartCallResponse) = 10,
@@ -184,12 +317,25 @@ pub enum Response {
 
     GetMessages(GetMessagesResponse) = 20,
     SendMessage(SendMessageResponse) = 22,
+    EditMessage(EditMessageResponse) = 23,
+    DeleteMessage(DeleteMessageResponse) = 24,
+    GetMessageRevisions(GetMessageRevisionsResponse) = 25,
+    AddReaction(AddReactionResponse) = 26,
+    RemoveReaction(RemoveReactionResponse) = 27,
+    GetReactions(GetReactionsResponse) = 28,
+    StartTyping(StartTypingResponse) = 29,
 
     GetChannel(GetChannelResponse) = 30,
     GetChannels(GetChannelsResponse) = 31,
-    // CreateChannel(CreateChannelResponse) = 32,
-    // EditChannel(EditChannelResponse) = 33,
-    // DeleteChannel(DeleteChannelResponse) = 34,
+    CreateChannel(CreateChannelResponse) = 32,
+    EditChannel(EditChannelResponse) = 33,
+    DeleteChannel(DeleteChannelResponse) = 34,
+    CreateThread(CreateThreadResponse) = 35,
+    GetThreads(GetThreadsResponse) = 36,
+    AckChannel(AckChannelResponse) = 37,
+    SetChannelPermissionOverride(SetChannelPermissionOverrideResponse) = 38,
+    DeleteChannelPermissionOverride(DeleteChannelPermissionOverrideResponse) = 39,
+
     GetSpace(GetSpaceResponse) = 40,
     CreateSpace(CreateSpaceResponse) = 41,
     EditSpace(EditSpaceResponse) = 42,
@@ -197,6 +343,9 @@ pub enum Response {
     JoinSpace(JoinSpaceResponse) = 44,
     LeaveSpace(LeaveSpaceResponse) = 45,
     GetSpaces(GetSpacesResponse) = 46,
+    ReorderChannels(ReorderChannelsResponse) = 47,
+
+    SetDirectMessagePrivacy(SetDirectMessagePrivacyResponse) = 56,
 
     CreateInvite(CreateInviteResponse) = 60,
     DeleteInvite(DeleteInviteResponse) = 61,
@@ -206,6 +355,35 @@ pub enum Response {
     CreateRole(CreateRoleResponse) = 70,
     EditRole(EditRoleResponse) = 71,
     DeleteRole(DeleteRoleResponse) = 72,
+
+    CreateEmoji(CreateEmojiResponse) = 80,
+    EditEmoji(EditEmojiResponse) = 81,
+    DeleteEmoji(DeleteEmojiResponse) = 82,
+    GetEmojis(GetEmojisResponse) = 83,
+
+    CreateUpload(CreateUploadResponse) = 90,
+    UploadChunk(UploadChunkResponse) = 91,
+    CompleteUpload(CompleteUploadResponse) = 92,
+    GetAttachment(GetAttachmentResponse) = 93,
+
+    PinMessage(PinMessageResponse) = 100,
+    UnpinMessage(UnpinMessageResponse) = 101,
+    GetPinnedMessages(GetPinnedMessagesResponse) = 102,
+    SearchMessages(SearchMessagesResponse) = 103,
+    BulkDeleteMessages(BulkDeleteMessagesResponse) = 104,
+    GetScheduledMessages(GetScheduledMessagesResponse) = 105,
+    CancelScheduledMessage(CancelScheduledMessageResponse) = 106,
+
+    PublishKeys(PublishKeysResponse) = 110,
+    AddPrekeys(AddPrekeysResponse) = 111,
+    GetKeyBundle(GetKeyBundleResponse) = 112,
+    EnableChannelEncryption(EnableChannelEncryptionResponse) = 113,
+
+    AddUserToChannel(AddUserToChannelResponse) = 120,
+    RemoveUserFromChannel(RemoveUserFromChannelResponse) = 121,
+    TransferChannelOwnership(TransferChannelOwnershipResponse) = 122,
+    LeaveChannel(LeaveChannelResponse) = 123,
+    OpenDirectMessage(OpenDirectMessageResponse) = 124,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -228,6 +406,25 @@ pub enum Event {
 
     // WebRTC: 10-19
     NewMessage(NewMessageEvent) = 21,
+    MessageUpdated(MessageUpdatedEvent) = 22,
+    MessageDeleted(MessageDeletedEvent) = 23,
+    ReactionAdded(ReactionAddedEvent) = 24,
+    ReactionRemoved(ReactionRemovedEvent) = 25,
+    Mentioned(MentionedEvent) = 26,
+    TypingStarted(TypingStartedEvent) = 27,
+    MessagesBulkDeleted(MessagesBulkDeletedEvent) = 28,
+    ScheduledMessageFailed(ScheduledMessageFailedEvent) = 29,
+
+    ChannelCreated(ChannelCreatedEvent) = 32,
+    ChannelUpdated(ChannelUpdatedEvent) = 33,
+    ChannelDeleted(ChannelDeletedEvent) = 34,
+    ThreadCreated(ThreadCreatedEvent) = 35,
+    ChannelAcked(ChannelAckedEvent) = 37,
+    ChannelsReordered(ChannelsReorderedEvent) = 47,
+
+    EmojiCreated(EmojiCreatedEvent) = 80,
+    EmojiUpdated(EmojiUpdatedEvent) = 81,
+    EmojiDeleted(EmojiDeletedEvent) = 82,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -248,6 +445,132 @@ pub struct HelloEvent {
 pub struct NewMessageEvent {
     message: Message,
     channel_id: String,
+    nonce: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageUpdatedEvent {
+    pub(crate) message: Message,
+    pub(crate) channel_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageDeletedEvent {
+    pub(crate) message_id: String,
+    pub(crate) channel_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MentionedEvent {
+    message: Message,
+    channel_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingStartedEvent {
+    channel_id: String,
+    user_id: String,
+    expires_at: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagesBulkDeletedEvent {
+    message_ids: Vec<String>,
+    channel_id: String,
+}
+
+// Sent to the author of a scheduled message that could not be sent
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessageFailedEvent {
+    pub(crate) id: String,
+    pub(crate) channel_id: String,
+    pub(crate) error: Error,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionAddedEvent {
+    message_id: String,
+    channel_id: String,
+    user_id: String,
+    emoji: ReactionEmoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionRemovedEvent {
+    message_id: String,
+    channel_id: String,
+    user_id: String,
+    emoji: ReactionEmoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelCreatedEvent {
+    channel: Channel,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelUpdatedEvent {
+    channel: Channel,
+}
+
+// Threads of the channel are deleted along with it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelDeletedEvent {
+    channel_id: String,
+    space_id: Option<String>,
+    thread_ids: Vec<String>,
+}
+
+// The full order of the space's channels
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelsReorderedEvent {
+    space_id: String,
+    channel_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiCreatedEvent {
+    pub(crate) emoji: Emoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiUpdatedEvent {
+    pub(crate) emoji: Emoji,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiDeletedEvent {
+    pub(crate) id: String,
+    pub(crate) space_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadCreatedEvent {
+    thread: Channel,
+    channel_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelAckedEvent {
+    channel_id: String,
+    message_id: String,
 }
 
 pub enum CreateChannelType {
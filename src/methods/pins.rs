@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    services::{
+        database::{
+            channels::Channel,
+            messages::{Message, SystemMessage},
+            users::User,
+        },
+        dispatch::dispatch_event,
+        permissions::Permission,
+        socket::RpcClient,
+    },
+};
+
+use super::{
+    authentication::check_authenticated,
+    messages::{check_can_view, send_system_message},
+    Event, MessageUpdatedEvent, Respond, Response,
+};
+
+// Anyone in a private or group channel may pin, space channels need ManageMessages
+async fn check_can_pin(user: &User, channel: &Channel) -> Result<()> {
+    check_can_view(user, channel).await?;
+    if channel.space_id().is_some()
+        && !channel
+            .has_permission(&user.id, Permission::ManageMessages)
+            .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::ManageMessages,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinMessageMethod {
+    message_id: String,
+}
+
+#[async_trait]
+impl Respond for PinMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_pin(&user, &channel).await?;
+        if message.system.is_some() {
+            return Err(Error::NotFound);
+        }
+        let message = message.pin().await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::MessageUpdated(MessageUpdatedEvent {
+                message: message.clone(),
+                channel_id: message.channel_id.clone(),
+            }),
+        )
+        .await?;
+        send_system_message(
+            &channel,
+            &user.id,
+            SystemMessage::MessagePinned {
+                message_id: message.id.clone(),
+            },
+        )
+        .await?;
+        Ok(Response::PinMessage(PinMessageResponse { message }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinMessageResponse {
+    message: Message,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpinMessageMethod {
+    message_id: String,
+}
+
+#[async_trait]
+impl Respond for UnpinMessageMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_pin(&user, &channel).await?;
+        let message = message.unpin().await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::MessageUpdated(MessageUpdatedEvent {
+                message: message.clone(),
+                channel_id: message.channel_id.clone(),
+            }),
+        )
+        .await?;
+        send_system_message(
+            &channel,
+            &user.id,
+            SystemMessage::MessageUnpinned {
+                message_id: message.id.clone(),
+            },
+        )
+        .await?;
+        Ok(Response::UnpinMessage(UnpinMessageResponse { message }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpinMessageResponse {
+    message: Message,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPinnedMessagesMethod {
+    channel_id: String,
+}
+
+#[async_trait]
+impl Respond for GetPinnedMessagesMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let channel = Channel::get(&self.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        let messages = Message::get_pinned(&self.channel_id).await?;
+        Ok(Response::GetPinnedMessages(GetPinnedMessagesResponse {
+            messages,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPinnedMessagesResponse {
+    messages: Vec<Message>,
+}
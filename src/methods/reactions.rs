@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    services::{
+        database::{
+            channels::Channel,
+            emojis::Emoji,
+            messages::Message,
+            reactions::{Reaction, ReactionEmoji},
+            users::User,
+        },
+        dispatch::dispatch_event,
+        permissions::Permission,
+        socket::RpcClient,
+    },
+};
+
+use super::{
+    authentication::check_authenticated, messages::check_can_view, Event, ReactionAddedEvent,
+    ReactionRemovedEvent, Respond, Response,
+};
+
+async fn check_emoji(user: &User, channel: &Channel, emoji: &ReactionEmoji) -> Result<()> {
+    match emoji {
+        ReactionEmoji::Unicode { value } => {
+            if emojis::get(value).is_none() {
+                return Err(Error::NotFound);
+            }
+        }
+        ReactionEmoji::Custom { id } => {
+            let emoji = Emoji::get(id).await?;
+            let available = match channel.space_id() {
+                Some(space_id) => &emoji.space_id == space_id,
+                None => user.in_space(&emoji.space_id).await?,
+            };
+            if !available {
+                return Err(Error::NotFound);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn check_can_react(user: &User, channel: &Channel) -> Result<()> {
+    check_can_view(user, channel).await?;
+    if channel.space_id().is_some()
+        && !channel
+            .has_permission(&user.id, Permission::UseReactions)
+            .await?
+    {
+        return Err(Error::MissingPermission {
+            permission: Permission::UseReactions,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddReactionMethod {
+    message_id: String,
+    emoji: ReactionEmoji,
+}
+
+#[async_trait]
+impl Respond for AddReactionMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_react(&user, &channel).await?;
+        check_emoji(&user, &channel, &self.emoji).await?;
+        Reaction::add(&message, &user.id, &self.emoji).await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::ReactionAdded(ReactionAddedEvent {
+                message_id: message.id.clone(),
+                channel_id: message.channel_id.clone(),
+                user_id: user.id.clone(),
+                emoji: self.emoji.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::AddReaction(AddReactionResponse {}))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddReactionResponse {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveReactionMethod {
+    message_id: String,
+    emoji: ReactionEmoji,
+    // Removing someone else's reaction requires ManageMessages
+    user_id: Option<String>,
+}
+
+#[async_trait]
+impl Respond for RemoveReactionMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        let target_id = self.user_id.clone().unwrap_or_else(|| user.id.clone());
+        if target_id != user.id
+            && !channel
+                .has_permission(&user.id, Permission::ManageMessages)
+                .await?
+        {
+            return Err(Error::MissingPermission {
+                permission: Permission::ManageMessages,
+            });
+        }
+        Reaction::remove(&message, &target_id, &self.emoji).await?;
+        dispatch_event(
+            channel.get_viewers().await?,
+            Event::ReactionRemoved(ReactionRemovedEvent {
+                message_id: message.id.clone(),
+                channel_id: message.channel_id.clone(),
+                user_id: target_id,
+                emoji: self.emoji.clone(),
+            }),
+        )
+        .await?;
+        Ok(Response::RemoveReaction(RemoveReactionResponse {}))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveReactionResponse {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetReactionsMethod {
+    message_id: String,
+    emoji: ReactionEmoji,
+    limit: Option<i64>,
+    after: Option<String>,
+}
+
+#[async_trait]
+impl Respond for GetReactionsMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        let message = Message::get(&self.message_id).await?;
+        let channel = Channel::get(&message.channel_id).await?;
+        check_can_view(&user, &channel).await?;
+        let user_ids =
+            Reaction::get_users(&message, &self.emoji, self.limit, self.after.clone()).await?;
+        Ok(Response::GetReactions(GetReactionsResponse { user_ids }))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetReactionsResponse {
+    user_ids: Vec<String>,
+}
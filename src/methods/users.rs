@@ -1 +1,41 @@
+use std::sync::Arc;
 
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::Result,
+    services::{database::users::DirectMessagePrivacy, socket::RpcClient},
+};
+
+use super::{authentication::check_authenticated, Respond, Response};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDirectMessagePrivacyMethod {
+    privacy: DirectMessagePrivacy,
+}
+
+#[async_trait]
+impl Respond for SetDirectMessagePrivacyMethod {
+    async fn respond(
+        &self,
+        clients: Arc<DashMap<String, RpcClient>>,
+        id: String,
+    ) -> Result<Response> {
+        let user = check_authenticated(clients, &id)?;
+        user.set_direct_message_privacy(&self.privacy).await?;
+        Ok(Response::SetDirectMessagePrivacy(
+            SetDirectMessagePrivacyResponse {
+                privacy: self.privacy.clone(),
+            },
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDirectMessagePrivacyResponse {
+    privacy: DirectMessagePrivacy,
+}
@@ -0,0 +1,485 @@
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    errors::{Error, Result},
+    services::{environment::MAX_ATTACHMENT_SIZE, storage::get_storage},
+};
+
+// Files are uploaded in chunks over the socket, then assembled into a
+// single object once every byte has been received
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub uploader_id: String,
+    pub created_at: i64,
+    pub received: i64,
+    pub chunk_count: u32,
+    pub completed: bool,
+    pub message_id: Option<String>,
+    pub channel_id: Option<String>,
+    // Set when the file is the image of a custom emoji
+    #[serde(default)]
+    pub emoji_id: Option<String>,
+    // Set while the file is held by a message that is yet to be sent
+    #[serde(default)]
+    pub scheduled_id: Option<String>,
+}
+
+// Metadata of an attachment as shown on a message
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageAttachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl From<Attachment> for MessageAttachment {
+    fn from(attachment: Attachment) -> Self {
+        MessageAttachment {
+            id: attachment.id,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            width: attachment.width,
+            height: attachment.height,
+        }
+    }
+}
+
+fn sanitize_filename(filename: &str) -> Result<String> {
+    let filename = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim();
+    if filename.is_empty() {
+        return Err(Error::NameEmpty);
+    }
+    if filename.len() > 255 {
+        return Err(Error::NameTooLong);
+    }
+    Ok(filename.to_owned())
+}
+
+impl Attachment {
+    fn key(&self) -> String {
+        format!("attachments/{}", self.id)
+    }
+
+    fn chunk_key(&self, index: u32) -> String {
+        format!("uploads/{}/{index}", self.id)
+    }
+
+    pub async fn create(
+        uploader_id: String,
+        filename: String,
+        size: i64,
+        content_type: Option<String>,
+    ) -> Result<Attachment> {
+        if size <= 0 {
+            return Err(Error::InvalidUpload);
+        }
+        if size > *MAX_ATTACHMENT_SIZE {
+            return Err(Error::FileTooLarge {
+                max_size: *MAX_ATTACHMENT_SIZE,
+            });
+        }
+        let attachment = Attachment {
+            id: Ulid::new().to_string(),
+            filename: sanitize_filename(&filename)?,
+            content_type: content_type.unwrap_or_else(|| "application/octet-stream".to_owned()),
+            size,
+            width: None,
+            height: None,
+            uploader_id,
+            created_at: chrono::Utc::now().timestamp_millis(),
+            received: 0,
+            chunk_count: 0,
+            completed: false,
+            message_id: None,
+            channel_id: None,
+            emoji_id: None,
+            scheduled_id: None,
+        };
+        let database = super::get_database();
+        database
+            .collection::<Attachment>("attachments")
+            .insert_one(attachment.clone())
+            .await?;
+        Ok(attachment)
+    }
+
+    pub async fn get(id: &String) -> Result<Attachment> {
+        let database = super::get_database();
+        let attachment = database
+            .collection::<Attachment>("attachments")
+            .find_one(doc! { "id": id })
+            .await?;
+        match attachment {
+            Some(attachment) => Ok(attachment),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    // Chunks must arrive in order; the offset guards against duplicates
+    pub async fn append_chunk(&self, offset: i64, data: Vec<u8>) -> Result<Attachment> {
+        let length = data.len() as i64;
+        if self.completed
+            || offset != self.received
+            || length == 0
+            || self.received + length > self.size
+        {
+            return Err(Error::InvalidUpload);
+        }
+        get_storage()
+            .put(&self.chunk_key(self.chunk_count), data)
+            .await?;
+        let database = super::get_database();
+        let attachment = database
+            .collection::<Attachment>("attachments")
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "received": offset,
+                    "completed": false,
+                },
+                doc! {
+                    "$inc": {
+                        "received": length,
+                        "chunkCount": 1,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        attachment.ok_or(Error::InvalidUpload)
+    }
+
+    pub async fn complete(&self) -> Result<Attachment> {
+        if self.completed || self.received != self.size {
+            return Err(Error::InvalidUpload);
+        }
+        let storage = get_storage();
+        let mut data = Vec::with_capacity(self.size as usize);
+        for index in 0..self.chunk_count {
+            data.extend(storage.get(&self.chunk_key(index)).await?);
+        }
+        if data.len() as i64 != self.size {
+            return Err(Error::InvalidUpload);
+        }
+        // Trust the file's contents over what the client claimed
+        let content_type = infer::get(&data)
+            .map(|kind| kind.mime_type().to_owned())
+            .unwrap_or_else(|| self.content_type.clone());
+        let (width, height) = if content_type.starts_with("image/") {
+            match imagesize::blob_size(&data) {
+                Ok(size) => (Some(size.width as u32), Some(size.height as u32)),
+                Err(_) => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+        storage.put(&self.key(), data).await?;
+        for index in 0..self.chunk_count {
+            storage.delete(&self.chunk_key(index)).await?;
+        }
+        let database = super::get_database();
+        let attachment = database
+            .collection::<Attachment>("attachments")
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "completed": false,
+                },
+                doc! {
+                    "$set": {
+                        "completed": true,
+                        "contentType": content_type,
+                        "width": width,
+                        "height": height,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        attachment.ok_or(Error::InvalidUpload)
+    }
+
+    pub async fn read(&self, offset: usize, length: usize) -> Result<Vec<u8>> {
+        if !self.completed {
+            return Err(Error::NotFound);
+        }
+        // Only the requested range is fetched, not the whole file
+        let offset = offset as u64;
+        let size = self.size as u64;
+        if offset >= size {
+            return Ok(Vec::new());
+        }
+        let length = (length as u64).min(size - offset);
+        get_storage().get_range(&self.key(), offset, length).await
+    }
+
+    // Completed uploads that the user has not yet sent in a message
+    pub async fn get_unclaimed(
+        ids: &[String],
+        uploader_id: &String,
+        scheduled_id: Option<&String>,
+    ) -> Result<Vec<Attachment>> {
+        let database = super::get_database();
+        let attachments: Vec<Attachment> = database
+            .collection::<Attachment>("attachments")
+            .find(doc! {
+                "id": { "$in": ids },
+                "uploaderId": uploader_id,
+                "completed": true,
+                "messageId": null,
+                "emojiId": null,
+                "scheduledId": scheduled_id,
+            })
+            .await?
+            .try_collect()
+            .await?;
+        if attachments.len() != ids.len() {
+            return Err(Error::InvalidUpload);
+        }
+        // Keep the order the user attached them in
+        let mut ordered = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(attachment) = attachments.iter().find(|a| &a.id == id) {
+                ordered.push(attachment.clone());
+            }
+        }
+        Ok(ordered)
+    }
+
+    // Takes the uploads for a message before it is stored, so that two
+    // messages can never end up sharing a file
+    pub async fn claim(
+        ids: &[String],
+        uploader_id: &String,
+        scheduled_id: Option<&String>,
+        message_id: &String,
+        channel_id: &String,
+    ) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let database = super::get_database();
+        let result = database
+            .collection::<Attachment>("attachments")
+            .update_many(
+                doc! {
+                    "id": { "$in": ids },
+                    "uploaderId": uploader_id,
+                    "completed": true,
+                    "messageId": null,
+                    "emojiId": null,
+                    "scheduledId": scheduled_id,
+                },
+                doc! {
+                    "$set": {
+                        "messageId": message_id,
+                        "channelId": channel_id,
+                    },
+                },
+            )
+            .await?;
+        if result.modified_count as usize != ids.len() {
+            Attachment::release(message_id).await?;
+            return Err(Error::InvalidUpload);
+        }
+        Ok(())
+    }
+
+    // Undoes a claim for a message that was never stored
+    pub async fn release(message_id: &String) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<Attachment>("attachments")
+            .update_many(
+                doc! { "messageId": message_id },
+                doc! {
+                    "$set": {
+                        "messageId": null,
+                        "channelId": null,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Holds the uploads of a scheduled message until it is sent, so that
+    // they are neither used elsewhere nor cleaned up as stale meanwhile
+    pub async fn reserve(
+        ids: &[String],
+        uploader_id: &String,
+        scheduled_id: &String,
+    ) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let database = super::get_database();
+        let result = database
+            .collection::<Attachment>("attachments")
+            .update_many(
+                doc! {
+                    "id": { "$in": ids },
+                    "uploaderId": uploader_id,
+                    "completed": true,
+                    "messageId": null,
+                    "emojiId": null,
+                    "scheduledId": null,
+                },
+                doc! {
+                    "$set": {
+                        "scheduledId": scheduled_id,
+                    },
+                },
+            )
+            .await?;
+        if result.modified_count as usize != ids.len() {
+            Attachment::release_reserved(scheduled_id).await?;
+            return Err(Error::InvalidUpload);
+        }
+        Ok(())
+    }
+
+    // Uploads of a scheduled message that will not be sent
+    pub async fn release_reserved(scheduled_id: &String) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<Attachment>("attachments")
+            .update_many(
+                doc! {
+                    "scheduledId": scheduled_id,
+                    "messageId": null,
+                },
+                doc! {
+                    "$set": {
+                        "scheduledId": null,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Only completed images that are not used anywhere else qualify
+    pub async fn claim_for_emoji(&self, emoji_id: &String) -> Result<()> {
+        if !self.completed || !self.content_type.starts_with("image/") {
+            return Err(Error::InvalidUpload);
+        }
+        let database = super::get_database();
+        let result = database
+            .collection::<Attachment>("attachments")
+            .update_one(
+                doc! {
+                    "id": &self.id,
+                    "completed": true,
+                    "messageId": null,
+                    "emojiId": null,
+                    "scheduledId": null,
+                },
+                doc! {
+                    "$set": {
+                        "emojiId": emoji_id,
+                    },
+                },
+            )
+            .await?;
+        if result.modified_count == 0 {
+            return Err(Error::InvalidUpload);
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<Attachment>("attachments")
+            .delete_one(doc! { "id": &self.id })
+            .await?;
+        self.delete_files().await
+    }
+
+    async fn delete_files(&self) -> Result<()> {
+        let storage = get_storage();
+        if self.completed {
+            storage.delete(&self.key()).await?;
+        } else {
+            for index in 0..self.chunk_count {
+                storage.delete(&self.chunk_key(index)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Uploads that were never sent in a message, used for an emoji or held
+    // by a scheduled message; returns how many were removed
+    pub async fn delete_stale(created_before: i64) -> Result<u64> {
+        let database = super::get_database();
+        let collection = database.collection::<Attachment>("attachments");
+        let unused = doc! {
+            "messageId": null,
+            "emojiId": null,
+            "scheduledId": null,
+        };
+        let mut query = unused.clone();
+        query.insert("createdAt", doc! { "$lt": created_before });
+        let stale: Vec<Attachment> = collection
+            .find(query)
+            .with_options(FindOptions::builder().limit(100).build())
+            .await?
+            .try_collect()
+            .await?;
+        let mut count = 0;
+        for attachment in stale {
+            // Skip uploads that were claimed in the meantime
+            let mut query = unused.clone();
+            query.insert("id", &attachment.id);
+            if collection.find_one_and_delete(query).await?.is_some() {
+                attachment.delete_files().await?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub async fn delete_for_messages(message_ids: &[String]) -> Result<()> {
+        let database = super::get_database();
+        let collection = database.collection::<Attachment>("attachments");
+        let query = doc! { "messageId": { "$in": message_ids } };
+        let attachments: Vec<Attachment> =
+            collection.find(query.clone()).await?.try_collect().await?;
+        for attachment in &attachments {
+            get_storage().delete(&attachment.key()).await?;
+        }
+        collection.delete_many(query).await?;
+        Ok(())
+    }
+}
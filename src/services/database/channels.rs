@@ -1,13 +1,25 @@
-use futures_util::StreamExt;
-use mongodb::{bson::doc, options::FindOptions};
+use std::collections::HashMap;
+
+use futures_util::{StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
 use serde::{Deserialize, Serialize};
+use ulid::Ulid;
 
 use crate::{
     errors::{Error, Result},
-    services::permissions::PermissionSet,
+    services::{
+        environment::MAX_GROUP_MEMBERS,
+        permissions::{Permission, PermissionSet},
+    },
 };
 
-use super::messages::Message;
+use super::{
+    invites::Invite, members::Member, messages::Message, read_states::ReadState,
+    scheduled_messages::ScheduledMessage, spaces::Space,
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
@@ -17,6 +29,12 @@ pub enum Channel {
         initiator_id: String,
         target_id: String,
         scope_id: String, // scope: "global" or id
+        #[serde(default)]
+        encrypted: bool,
+        // Both user IDs in sorted order, unique so that a pair of users
+        // cannot end up with two channels
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pair_key: Option<String>,
     },
     GroupChannel {
         id: String,
@@ -25,20 +43,37 @@ pub enum Channel {
         owner_id: String,
         members: Vec<String>,
         scope_id: String,
+        #[serde(default)]
+        encrypted: bool,
     },
     InformationChannel {
         id: String,
         name: String,
+        #[serde(default)]
+        description: String,
         space_id: String,
         scope_id: String,
         permissions: Vec<PermissionOverride>,
+        #[serde(default)]
+        category_id: Option<String>,
+        #[serde(default)]
+        permissions_synced: bool,
     },
     AnnouncementChannel {
         id: String,
         name: String,
+        #[serde(default)]
+        description: String,
         space_id: String,
         scope_id: String,
         permissions: Vec<PermissionOverride>,
+        // Only members with this role may post, space administrators otherwise
+        #[serde(default)]
+        announcer_role_id: Option<String>,
+        #[serde(default)]
+        category_id: Option<String>,
+        #[serde(default)]
+        permissions_synced: bool,
     },
     ChatChannel {
         id: String,
@@ -48,10 +83,832 @@ pub enum Channel {
         scope_id: String,
         // TODO: permission checks
         permissions: Vec<PermissionOverride>,
+        #[serde(default)]
+        category_id: Option<String>,
+        // Synced channels use their category's overrides instead of their own
+        #[serde(default)]
+        permissions_synced: bool,
+        // Seconds members have to wait between messages, zero when disabled
+        #[serde(default)]
+        slowmode_seconds: u32,
+    },
+    // Groups other channels in a space; nothing can be posted in it
+    CategoryChannel {
+        id: String,
+        name: String,
+        space_id: String,
+        scope_id: String,
+        permissions: Vec<PermissionOverride>,
+    },
+    // Spawned from a message, access is inherited from the parent channel
+    ThreadChannel {
+        id: String,
+        name: String,
+        parent_id: String,
+        message_id: String,
+        space_id: Option<String>,
+        scope_id: String,
+        owner_id: String,
+        participants: Vec<String>,
+        archived: bool,
+        last_message_id: Option<String>,
+        last_activity_at: i64,
     },
 }
 
 impl Channel {
+    // Fails with AlreadyExists if the two users of a private channel
+    // already have one
+    pub async fn create(&self) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<Channel>("channels")
+            .insert_one(self.clone())
+            .await
+            .map_err(|e| match super::is_duplicate_key(&e) {
+                true => Error::AlreadyExists,
+                false => e.into(),
+            })?;
+        Ok(())
+    }
+
+    pub fn new_private(initiator_id: String, target_id: String, scope_id: String) -> Channel {
+        let mut pair = [&initiator_id, &target_id];
+        pair.sort();
+        let pair_key = format!("{}:{}", pair[0], pair[1]);
+        Channel::PrivateChannel {
+            id: Ulid::new().to_string(),
+            initiator_id,
+            target_id,
+            scope_id,
+            encrypted: false,
+            pair_key: Some(pair_key),
+        }
+    }
+
+    pub fn id(&self) -> &String {
+        match self {
+            Channel::PrivateChannel { id, .. }
+            | Channel::GroupChannel { id, .. }
+            | Channel::InformationChannel { id, .. }
+            | Channel::AnnouncementChannel { id, .. }
+            | Channel::ChatChannel { id, .. }
+            | Channel::CategoryChannel { id, .. }
+            | Channel::ThreadChannel { id, .. } => id,
+        }
+    }
+
+    pub fn space_id(&self) -> Option<&String> {
+        match self {
+            Channel::PrivateChannel { .. } | Channel::GroupChannel { .. } => None,
+            Channel::InformationChannel { space_id, .. }
+            | Channel::AnnouncementChannel { space_id, .. }
+            | Channel::ChatChannel { space_id, .. }
+            | Channel::CategoryChannel { space_id, .. } => Some(space_id),
+            Channel::ThreadChannel { space_id, .. } => space_id.as_ref(),
+        }
+    }
+
+    pub fn slowmode_seconds(&self) -> u32 {
+        match self {
+            Channel::ChatChannel {
+                slowmode_seconds, ..
+            } => *slowmode_seconds,
+            _ => 0,
+        }
+    }
+
+    pub async fn get_parent(&self) -> Result<Option<Channel>> {
+        match self {
+            Channel::ThreadChannel { parent_id, .. } => Ok(Some(Channel::get(parent_id).await?)),
+            _ => Ok(None),
+        }
+    }
+
+    // Threads follow their parent channel
+    pub async fn is_encrypted(&self) -> Result<bool> {
+        match self {
+            Channel::PrivateChannel { encrypted, .. } | Channel::GroupChannel { encrypted, .. } => {
+                Ok(*encrypted)
+            }
+            Channel::ThreadChannel { .. } => match self.get_parent().await? {
+                Some(parent) => Box::pin(parent.is_encrypted()).await,
+                None => Ok(false),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // Encryption cannot be turned off again once enabled
+    pub async fn enable_encryption(&self) -> Result<()> {
+        let result = super::get_database()
+            .collection::<Channel>("channels")
+            .update_one(
+                doc! {
+                    "id": self.id(),
+                    "type": { "$in": ["PRIVATE_CHANNEL", "GROUP_CHANNEL"] },
+                    "encrypted": { "$ne": true },
+                },
+                doc! {
+                    "$set": {
+                        "encrypted": true,
+                    },
+                },
+            )
+            .await?;
+        if result.modified_count == 0 {
+            return Err(Error::AlreadyExists);
+        }
+        Ok(())
+    }
+
+    // Overrides that apply to a space channel, taken from its category when synced
+    pub async fn permission_overrides(&self) -> Result<Option<Vec<PermissionOverride>>> {
+        match self {
+            Channel::InformationChannel {
+                permissions,
+                category_id,
+                permissions_synced,
+                ..
+            }
+            | Channel::AnnouncementChannel {
+                permissions,
+                category_id,
+                permissions_synced,
+                ..
+            }
+            | Channel::ChatChannel {
+                permissions,
+                category_id,
+                permissions_synced,
+                ..
+            } => {
+                if let (Some(category_id), true) = (category_id, permissions_synced) {
+                    if let Ok(Channel::CategoryChannel { permissions, .. }) =
+                        Channel::get(category_id).await
+                    {
+                        return Ok(Some(permissions));
+                    }
+                }
+                Ok(Some(permissions.clone()))
+            }
+            Channel::CategoryChannel { permissions, .. } => Ok(Some(permissions.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn has_permission(&self, user_id: &String, permission: Permission) -> Result<bool> {
+        if let Some(parent) = self.get_parent().await? {
+            return Box::pin(parent.has_permission(user_id, permission)).await;
+        }
+        match self.space_id() {
+            Some(space_id) => {
+                let space = Space::get(space_id).await?;
+                if !space.members.contains(user_id) {
+                    return Ok(false);
+                }
+                let member = Member::get_or_default(user_id, space_id).await?;
+                member.get_permission_in_channel(self, permission).await
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Users who receive events for this channel
+    pub async fn get_viewers(&self) -> Result<Vec<String>> {
+        match self {
+            Channel::PrivateChannel {
+                initiator_id,
+                target_id,
+                ..
+            } => Ok(vec![initiator_id.clone(), target_id.clone()]),
+            Channel::GroupChannel { members, .. } => Ok(members.clone()),
+            Channel::InformationChannel { space_id, .. }
+            | Channel::AnnouncementChannel { space_id, .. }
+            | Channel::ChatChannel { space_id, .. }
+            | Channel::CategoryChannel { space_id, .. } => {
+                // Everything is loaded once instead of once per member, since
+                // this runs for every event dispatched to the channel
+                let space = Space::get(space_id).await?;
+                let overrides = self.permission_overrides().await?;
+                let roles = space
+                    .get_roles()
+                    .await?
+                    .into_iter()
+                    .map(|r| (r.id.clone(), r))
+                    .collect::<HashMap<_, _>>();
+                let mut members = Member::get_all(space_id)
+                    .await?
+                    .into_iter()
+                    .map(|m| (m.id.clone(), m))
+                    .collect::<HashMap<_, _>>();
+                let mut viewers = Vec::new();
+                for member_id in &space.members {
+                    let member = members.remove(member_id).unwrap_or_else(|| Member {
+                        id: member_id.clone(),
+                        space_id: space_id.clone(),
+                        roles: Vec::new(),
+                    });
+                    if member.calculate_permission_in_channel(
+                        &space,
+                        &roles,
+                        overrides.as_deref(),
+                        Permission::ViewChannels,
+                    ) {
+                        viewers.push(member_id.clone());
+                    }
+                }
+                Ok(viewers)
+            }
+            Channel::ThreadChannel { parent_id, .. } => {
+                let parent = Channel::get(parent_id).await?;
+                Box::pin(parent.get_viewers()).await
+            }
+        }
+    }
+
+    pub async fn get_threads(&self, archived: bool) -> Result<Vec<Channel>> {
+        let database = super::get_database();
+        let threads = database
+            .collection::<Channel>("channels")
+            .find(doc! {
+                "type": "THREAD_CHANNEL",
+                "parent_id": self.id(),
+                "archived": archived,
+            })
+            .with_options(
+                FindOptions::builder()
+                    .sort(doc! { "last_activity_at": -1 })
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        Ok(threads)
+    }
+
+    pub async fn create_thread(
+        &self,
+        message: &Message,
+        name: String,
+        owner_id: String,
+    ) -> Result<Channel> {
+        let scope_id = match self {
+            Channel::PrivateChannel { scope_id, .. }
+            | Channel::GroupChannel { scope_id, .. }
+            | Channel::InformationChannel { scope_id, .. }
+            | Channel::AnnouncementChannel { scope_id, .. }
+            | Channel::ChatChannel { scope_id, .. } => scope_id.clone(),
+            Channel::CategoryChannel { .. } | Channel::ThreadChannel { .. } => {
+                return Err(Error::NotFound)
+            }
+        };
+        let thread = Channel::ThreadChannel {
+            id: Ulid::new().to_string(),
+            name,
+            parent_id: self.id().clone(),
+            message_id: message.id.clone(),
+            space_id: self.space_id().cloned(),
+            scope_id,
+            owner_id: owner_id.clone(),
+            participants: vec![owner_id],
+            archived: false,
+            last_message_id: None,
+            last_activity_at: chrono::Utc::now().timestamp_millis(),
+        };
+        // Claim the message first so that it can only spawn one thread
+        let claimed = super::get_database()
+            .collection::<Message>("messages")
+            .update_one(
+                doc! {
+                    "id": &message.id,
+                    "threadId": null,
+                },
+                doc! {
+                    "$set": {
+                        "threadId": thread.id(),
+                    },
+                },
+            )
+            .await?;
+        if claimed.modified_count == 0 {
+            return Err(Error::AlreadyExists);
+        }
+        thread.create().await?;
+        Ok(thread)
+    }
+
+    // Records activity in a thread, unarchiving it if necessary
+    pub async fn touch_thread(&self, user_id: &String, message_id: &String) -> Result<()> {
+        if let Channel::ThreadChannel { id, .. } = self {
+            super::get_database()
+                .collection::<Channel>("channels")
+                .update_one(
+                    doc! {
+                        "id": id,
+                    },
+                    doc! {
+                        "$addToSet": {
+                            "participants": user_id,
+                        },
+                        "$set": {
+                            "archived": false,
+                            "last_message_id": message_id,
+                            "last_activity_at": chrono::Utc::now().timestamp_millis(),
+                        },
+                    },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Includes threads spawned in the space's channels
+    pub async fn get_all_in_space(space_id: &String) -> Result<Vec<Channel>> {
+        let database = super::get_database();
+        let channels = database
+            .collection::<Channel>("channels")
+            .find(doc! {
+                "space_id": space_id,
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(channels)
+    }
+
+    // Whether both users are in the same private or group channel
+    pub async fn shares_direct(user_id: &String, other_id: &String) -> Result<bool> {
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one(doc! {
+                "$or": [
+                    { "type": "PRIVATE_CHANNEL", "initiator_id": user_id, "target_id": other_id },
+                    { "type": "PRIVATE_CHANNEL", "initiator_id": other_id, "target_id": user_id },
+                    { "type": "GROUP_CHANNEL", "members": { "$all": [user_id, other_id] } },
+                ],
+            })
+            .await?;
+        Ok(channel.is_some())
+    }
+
+    // Private and group channels the user is part of, with their threads
+    pub async fn get_direct(user_id: &String) -> Result<Vec<Channel>> {
+        let database = super::get_database();
+        let collection = database.collection::<Channel>("channels");
+        let mut channels: Vec<Channel> = collection
+            .find(doc! {
+                "$or": [
+                    { "type": "PRIVATE_CHANNEL", "initiator_id": user_id },
+                    { "type": "PRIVATE_CHANNEL", "target_id": user_id },
+                    { "type": "GROUP_CHANNEL", "members": user_id },
+                ],
+            })
+            .await?
+            .try_collect()
+            .await?;
+        let parent_ids = channels.iter().map(|c| c.id().clone()).collect::<Vec<_>>();
+        let threads: Vec<Channel> = collection
+            .find(doc! {
+                "type": "THREAD_CHANNEL",
+                "parent_id": { "$in": parent_ids },
+            })
+            .await?
+            .try_collect()
+            .await?;
+        channels.extend(threads);
+        Ok(channels)
+    }
+
+    pub async fn get_private(user_id: &String, other_id: &String) -> Result<Option<Channel>> {
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one(doc! {
+                "type": "PRIVATE_CHANNEL",
+                "$or": [
+                    { "initiator_id": user_id, "target_id": other_id },
+                    { "initiator_id": other_id, "target_id": user_id },
+                ],
+            })
+            .await?;
+        Ok(channel)
+    }
+
+    // Concurrent opens between the same users all get the same channel; the
+    // flag tells whether this call created it
+    pub async fn open_private(
+        initiator_id: &String,
+        target_id: &String,
+    ) -> Result<(Channel, bool)> {
+        let channel =
+            Channel::new_private(initiator_id.clone(), target_id.clone(), "global".to_owned());
+        match channel.create().await {
+            Ok(()) => Ok((channel, true)),
+            Err(Error::AlreadyExists) => {
+                let channel = Channel::get_private(initiator_id, target_id)
+                    .await?
+                    .ok_or(Error::NotFound)?;
+                Ok((channel, false))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    // Private channels and threads have nothing to edit here
+    pub async fn update(
+        &self,
+        name: Option<String>,
+        description: Option<String>,
+        slowmode_seconds: Option<u32>,
+    ) -> Result<Channel> {
+        if matches!(
+            self,
+            Channel::PrivateChannel { .. } | Channel::ThreadChannel { .. }
+        ) {
+            return Err(Error::NotFound);
+        }
+        let mut update = doc! {};
+        if let Some(name) = name {
+            update.insert("name", name);
+        }
+        // Categories have no description
+        if let Some(description) = description {
+            if !matches!(self, Channel::CategoryChannel { .. }) {
+                update.insert("description", description);
+            }
+        }
+        // Only chat channels have slow mode
+        if let Some(slowmode_seconds) = slowmode_seconds {
+            if matches!(self, Channel::ChatChannel { .. }) {
+                update.insert("slowmode_seconds", slowmode_seconds as i64);
+            }
+        }
+        if update.is_empty() {
+            return Ok(self.clone());
+        }
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                },
+                doc! {
+                    "$set": update,
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        channel.ok_or(Error::NotFound)
+    }
+
+    // Channels of a category that use the category's overrides as their own
+    pub async fn get_synced_children(&self) -> Result<Vec<Channel>> {
+        if !matches!(self, Channel::CategoryChannel { .. }) {
+            return Ok(Vec::new());
+        }
+        let database = super::get_database();
+        let channels = database
+            .collection::<Channel>("channels")
+            .find(doc! {
+                "category_id": self.id(),
+                "permissions_synced": true,
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(channels)
+    }
+
+    fn permissions_synced(&self) -> bool {
+        matches!(
+            self,
+            Channel::InformationChannel {
+                permissions_synced: true,
+                ..
+            } | Channel::AnnouncementChannel {
+                permissions_synced: true,
+                ..
+            } | Channel::ChatChannel {
+                permissions_synced: true,
+                ..
+            }
+        )
+    }
+
+    // A channel that stops syncing keeps the overrides it had, the same as
+    // when one of its overrides is changed
+    pub async fn move_to_category(
+        &self,
+        category_id: Option<String>,
+        synced: bool,
+    ) -> Result<CategoryMove> {
+        let synced = category_id.is_some() && synced;
+        let permissions = if self.permissions_synced() && !synced {
+            Some(self.permission_overrides().await?.unwrap_or_default())
+        } else {
+            None
+        };
+        Ok(CategoryMove {
+            channel_id: self.id().clone(),
+            category_id,
+            synced,
+            permissions,
+        })
+    }
+
+    // Copies the category's overrides into a synced channel so that they
+    // can be changed without affecting the rest of the category
+    async fn unsync_permissions(&self) -> Result<()> {
+        if !self.permissions_synced() {
+            return Ok(());
+        }
+        let permissions = self.permission_overrides().await?.unwrap_or_default();
+        super::get_database()
+            .collection::<Channel>("channels")
+            .update_one(
+                doc! {
+                    "id": self.id(),
+                },
+                doc! {
+                    "$set": {
+                        "permissions": bson::to_bson(&permissions)?,
+                        "permissions_synced": false,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Replaces the override for the role or member, or adds it if there is none
+    pub async fn set_permission_override(&self, permission: PermissionOverride) -> Result<Channel> {
+        if !matches!(
+            self,
+            Channel::InformationChannel { .. }
+                | Channel::AnnouncementChannel { .. }
+                | Channel::ChatChannel { .. }
+                | Channel::CategoryChannel { .. }
+        ) {
+            return Err(Error::NotFound);
+        }
+        self.unsync_permissions().await?;
+        let database = super::get_database();
+        let channels = database.collection::<Channel>("channels");
+        let existing = doc! {
+            "$elemMatch": {
+                "id": &permission.id,
+                "entityType": bson::to_bson(&permission.entity_type)?,
+            },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let channel = channels
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "permissions": existing.clone(),
+                },
+                doc! {
+                    "$set": {
+                        "permissions.$": bson::to_bson(&permission)?,
+                    },
+                },
+            )
+            .with_options(options.clone())
+            .await?;
+        if let Some(channel) = channel {
+            return Ok(channel);
+        }
+        let channel = channels
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "permissions": { "$not": existing },
+                },
+                doc! {
+                    "$push": {
+                        "permissions": bson::to_bson(&permission)?,
+                    },
+                },
+            )
+            .with_options(options)
+            .await?;
+        // Only happens if another request added the same override meanwhile
+        channel.ok_or(Error::AlreadyExists)
+    }
+
+    pub async fn delete_permission_override(
+        &self,
+        id: &String,
+        entity_type: &EntityType,
+    ) -> Result<Channel> {
+        self.unsync_permissions().await?;
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "permissions": {
+                        "$elemMatch": {
+                            "id": id,
+                            "entityType": bson::to_bson(entity_type)?,
+                        },
+                    },
+                },
+                doc! {
+                    "$pull": {
+                        "permissions": {
+                            "id": id,
+                            "entityType": bson::to_bson(entity_type)?,
+                        },
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        channel.ok_or(Error::NotFound)
+    }
+
+    // The size check is part of the filter so concurrent adds cannot
+    // overfill the group
+    pub async fn add_group_member(&self, user_id: &String) -> Result<Channel> {
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "type": "GROUP_CHANNEL",
+                    "members": { "$ne": user_id },
+                    format!("members.{}", MAX_GROUP_MEMBERS.saturating_sub(1)): { "$exists": false },
+                },
+                doc! {
+                    "$push": {
+                        "members": user_id,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        if let Some(channel) = channel {
+            return Ok(channel);
+        }
+        match Channel::get(self.id()).await? {
+            Channel::GroupChannel { members, .. } if members.contains(user_id) => {
+                Err(Error::AlreadyExists)
+            }
+            Channel::GroupChannel { .. } => Err(Error::ChannelFull),
+            _ => Err(Error::NotFound),
+        }
+    }
+
+    // The owner has to hand the group over before they can be removed
+    pub async fn remove_group_member(&self, user_id: &String) -> Result<Channel> {
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "type": "GROUP_CHANNEL",
+                    "members": user_id,
+                    "owner_id": { "$ne": user_id },
+                },
+                doc! {
+                    "$pull": {
+                        "members": user_id,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        channel.ok_or(Error::NotFound)
+    }
+
+    pub async fn transfer_group_ownership(
+        &self,
+        owner_id: &String,
+        new_owner_id: &String,
+    ) -> Result<Channel> {
+        let database = super::get_database();
+        let channel = database
+            .collection::<Channel>("channels")
+            .find_one_and_update(
+                doc! {
+                    "id": self.id(),
+                    "type": "GROUP_CHANNEL",
+                    "owner_id": owner_id,
+                    "members": new_owner_id,
+                },
+                doc! {
+                    "$set": {
+                        "owner_id": new_owner_id,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        channel.ok_or(Error::NotFound)
+    }
+
+    // Removes the channel and its threads along with everything posted in
+    // them, returning the IDs of the removed channels
+    pub async fn delete(&self) -> Result<Vec<String>> {
+        let database = super::get_database();
+        let channels = database.collection::<Channel>("channels");
+        let mut channel_ids = vec![self.id().clone()];
+        let threads: Vec<Document> = database
+            .collection::<Document>("channels")
+            .find(doc! {
+                "type": "THREAD_CHANNEL",
+                "parent_id": self.id(),
+            })
+            .projection(doc! { "id": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        channel_ids.extend(
+            threads
+                .iter()
+                .filter_map(|t| t.get_str("id").ok().map(str::to_owned)),
+        );
+        channels
+            .delete_many(doc! { "id": { "$in": &channel_ids } })
+            .await?;
+        // Channels in a deleted category keep the overrides they had
+        if let Channel::CategoryChannel {
+            id, permissions, ..
+        } = self
+        {
+            channels
+                .update_many(
+                    doc! {
+                        "category_id": id,
+                        "permissions_synced": true,
+                    },
+                    doc! {
+                        "$set": {
+                            "permissions": bson::to_bson(permissions)?,
+                        },
+                    },
+                )
+                .await?;
+            channels
+                .update_many(
+                    doc! {
+                        "category_id": id,
+                    },
+                    doc! {
+                        "$set": {
+                            "category_id": null,
+                            "permissions_synced": false,
+                        },
+                    },
+                )
+                .await?;
+        }
+        Message::delete_in_channels(&channel_ids).await?;
+        let related = doc! { "channelId": { "$in": &channel_ids } };
+        database
+            .collection::<Invite>("invites")
+            .delete_many(related.clone())
+            .await?;
+        database
+            .collection::<ReadState>("read_states")
+            .delete_many(related)
+            .await?;
+        database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .delete_many(doc! { "draft.channelId": { "$in": &channel_ids } })
+            .await?;
+        Ok(channel_ids)
+    }
+
     pub async fn get(id: &String) -> Result<Channel> {
         let database = super::get_database();
         let channel = database
@@ -72,42 +929,70 @@ impl Channel {
         before: Option<String>,
         after: Option<String>,
     ) -> Result<Vec<Message>> {
-        match self {
-            Channel::AnnouncementChannel { id, .. } | Channel::ChatChannel { id, .. } => {
-                let database = super::get_database();
-                let limit = limit.unwrap_or(50);
-                let mut query = doc! { "channelId": id };
-                if let Some(before) = before {
-                    query.insert("id", doc! { "$lt": before });
-                }
-                if let Some(after) = after {
-                    query.insert("id", doc! { "$gt": after });
-                }
-                let options = FindOptions::builder()
-                    .sort(doc! {
-                        "id": if latest.unwrap_or(false) { -1 } else { 1 }
-                    })
-                    .limit(limit)
-                    .build();
-                let messages: Vec<_> = database
-                    .collection::<Message>("messages")
-                    .find(query)
-                    .with_options(options)
-                    .await?
-                    .collect()
-                    .await;
-                let messages = messages
-                    .into_iter()
-                    .map(|m| m.map_err(|e| e.into()))
-                    .collect::<Result<Vec<_>>>()?;
-
-                Ok(messages)
-            }
-            _ => Err(Error::NotFound),
+        let database = super::get_database();
+        let limit = limit.unwrap_or(50);
+        let mut query = doc! { "channelId": self.id() };
+        let mut range = doc! {};
+        if let Some(before) = before {
+            range.insert("$lt", before);
         }
+        if let Some(after) = after {
+            range.insert("$gt", after);
+        }
+        if !range.is_empty() {
+            query.insert("id", range);
+        }
+        let options = FindOptions::builder()
+            .sort(doc! {
+                "id": if latest.unwrap_or(false) { -1 } else { 1 }
+            })
+            .limit(limit)
+            .build();
+        let messages: Vec<_> = database
+            .collection::<Message>("messages")
+            .find(query)
+            .with_options(options)
+            .await?
+            .collect()
+            .await;
+        let messages = messages
+            .into_iter()
+            .map(|m| m.map_err(|e| e.into()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(messages)
     }
 }
 
+pub async fn archive_inactive_threads(inactive_since: i64) -> Result<u64> {
+    let database = super::get_database();
+    let result = database
+        .collection::<Channel>("channels")
+        .update_many(
+            doc! {
+                "type": "THREAD_CHANNEL",
+                "archived": false,
+                "last_activity_at": { "$lt": inactive_since },
+            },
+            doc! {
+                "$set": {
+                    "archived": true,
+                },
+            },
+        )
+        .await?;
+    Ok(result.modified_count)
+}
+
+// Moves a channel into a category, or out of it when there is none
+#[derive(Clone, Debug)]
+pub struct CategoryMove {
+    pub channel_id: String,
+    pub category_id: Option<String>,
+    pub synced: bool,
+    pub permissions: Option<Vec<PermissionOverride>>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PermissionOverride {
@@ -123,59 +1008,3 @@ pub enum EntityType {
     Role,
     Member,
 }
-
-// pub async fn create_channel(
-//     channel_type: String,
-//     name: Option<String>,
-//     description: Option<String>,
-//     space_id: Option<String>,
-//     members: Option<Vec<String>>,
-//     initiator_id: Option<String>,
-//     peer_id: Option<String>,
-// ) -> Result<Channel> {
-//     let database = super::get_database();
-//     let channel = match channel_type.as_str() {
-//         "PRIVATE" => Channel::PrivateChannel {
-//             id: super::generate_ulid(),
-//             initiator_id: initiator_id.unwrap(),
-//             peer_id: peer_id.unwrap(),
-//             scope_id: "global".to_owned(),
-//         },
-//         "GROUP" => Channel::GroupChannel {
-//             id: super::generate_ulid(),
-//             name: name.unwrap(),
-//             description: description.unwrap_or("".to_string()),
-//             owner_id: initiator_id.unwrap(),
-//             members: members.unwrap(),
-//             scope_id: "global".to_owned(),
-//         },
-//         "INFORMATION" => Channel::InformationChannel {
-//             id: super::generate_ulid(),
-//             name: name.unwrap(),
-//             space_id: space_id.unwrap(),
-//             scope_id: "global".to_owned(),
-//         },
-//         "ANNOUNCEMENT" => Channel::AnnouncementChannel {
-//             id: super::generate_ulid(),
-//             name: name.unwrap(),
-//             space_id: space_id.unwrap(),
-//             scope_id: "global".to_owned(),
-//         },
-//         "CHAT" => Channel::ChatChannel {
-//             id: super::generate_ulid(),
-//             name: name.unwrap(),
-//             description: description.unwrap_or("".to_string()),
-//             space_id: space_id.unwrap(),
-//             scope_id: "global".to_owned(),
-//         },
-//         _ => return Err(Error::BadRequest),
-//     };
-//     database
-//         .collection::<Channel>("channels")
-//         .insert_one(
-//             channel.clone(),
-//             None,
-//         )
-//         .await?;
-//     Ok(channel)
-// }
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+
+use super::roles::Color;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EmbedKind {
+    // Unfurled by the server from a link in the content
+    Link,
+    // Sent explicitly with the message
+    Rich,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Embed {
+    pub kind: EmbedKind,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub image_url: Option<String>,
+    pub color: Option<Color>,
+}
+
+fn is_http_url(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|u| u.scheme() == "http" || u.scheme() == "https")
+}
+
+impl Embed {
+    pub fn validate_rich(embeds: Vec<Embed>) -> Result<Vec<Embed>> {
+        if embeds.len() > 10 {
+            return Err(Error::InvalidEmbed);
+        }
+        let mut validated = Vec::new();
+        for mut embed in embeds {
+            embed.kind = EmbedKind::Rich;
+            let too_long = |field: &Option<String>, max: usize| {
+                field.as_ref().is_some_and(|f| f.chars().count() > max)
+            };
+            if too_long(&embed.title, 256)
+                || too_long(&embed.site_name, 256)
+                || too_long(&embed.description, 4096)
+            {
+                return Err(Error::InvalidEmbed);
+            }
+            if [&embed.url, &embed.image_url]
+                .into_iter()
+                .flatten()
+                .any(|url| !is_http_url(url))
+            {
+                return Err(Error::InvalidEmbed);
+            }
+            if embed.title.is_none() && embed.description.is_none() && embed.image_url.is_none() {
+                return Err(Error::InvalidEmbed);
+            }
+            validated.push(embed);
+        }
+        Ok(validated)
+    }
+}
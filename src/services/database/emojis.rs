@@ -1,8 +1,164 @@
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    errors::{Error, Result},
+    services::environment::MAX_EMOJI_COUNT,
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Emoji {
-    id: String,
-    name: String,
-    file_id: String,
+    pub id: String,
+    pub name: String,
+    pub file_id: String,
+    pub space_id: String,
+    pub creator_id: String,
+}
+
+// Reference to a custom emoji used in a message
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEmoji {
+    pub id: String,
+    pub name: String,
+}
+
+impl From<Emoji> for MessageEmoji {
+    fn from(emoji: Emoji) -> Self {
+        MessageEmoji {
+            id: emoji.id,
+            name: emoji.name,
+        }
+    }
+}
+
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::NameEmpty);
+    }
+    if name.len() > 32 {
+        return Err(Error::NameTooLong);
+    }
+    if name.len() < 2 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::InvalidName);
+    }
+    Ok(())
+}
+
+fn map_duplicate_name(error: mongodb::error::Error) -> Error {
+    match super::is_duplicate_key(&error) {
+        true => Error::AlreadyExists,
+        false => error.into(),
+    }
+}
+
+impl Emoji {
+    pub async fn create(
+        space_id: &String,
+        name: String,
+        file_id: String,
+        creator_id: String,
+    ) -> Result<Emoji> {
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        let emoji = Emoji {
+            id: Ulid::new().to_string(),
+            name,
+            file_id,
+            space_id: space_id.clone(),
+            creator_id,
+        };
+        // Names are kept unique per space by an index
+        emojis
+            .insert_one(emoji.clone())
+            .await
+            .map_err(map_duplicate_name)?;
+        // Counted after inserting, so that concurrent creates cannot both
+        // slip in under the limit
+        let count = emojis
+            .count_documents(doc! {
+                "space_id": space_id,
+            })
+            .await?;
+        if count > *MAX_EMOJI_COUNT {
+            emoji.delete().await?;
+            return Err(Error::EmojiLimitReached);
+        }
+        Ok(emoji)
+    }
+
+    pub async fn get(id: &String) -> Result<Emoji> {
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        let emoji = emojis
+            .find_one(doc! {
+                "id": id,
+            })
+            .await?;
+        match emoji {
+            Some(emoji) => Ok(emoji),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    pub async fn get_all(space_id: &String) -> Result<Vec<Emoji>> {
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        let emojis = emojis
+            .find(doc! {
+                "space_id": space_id,
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(emojis)
+    }
+
+    // Unknown names are left as plain text
+    pub async fn resolve(space_id: &String, names: &[String]) -> Result<Vec<MessageEmoji>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        let emojis: Vec<Emoji> = emojis
+            .find(doc! {
+                "space_id": space_id,
+                "name": { "$in": names },
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(emojis.into_iter().map(MessageEmoji::from).collect())
+    }
+
+    pub async fn update(&self, name: Option<String>) -> Result<Emoji> {
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        let mut emoji = self.clone();
+        if let Some(name) = name {
+            emoji.name = name;
+        }
+        emojis
+            .update_one(
+                doc! {
+                    "id": &self.id,
+                },
+                doc! {
+                    "$set": {
+                        "name": &emoji.name,
+                    },
+                },
+            )
+            .await
+            .map_err(map_duplicate_name)?;
+        Ok(emoji)
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        emojis
+            .delete_one(doc! {
+                "id": &self.id,
+            })
+            .await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,146 @@
+use mongodb::{
+    bson::{self, doc},
+    options::{FindOneAndUpdateOptions, ReplaceOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+
+const MAX_KEY_SIZE: usize = 256;
+const MAX_ONE_TIME_PREKEYS: usize = 100;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prekey {
+    pub id: u32,
+    #[serde(with = "serde_bytes")]
+    pub key: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedPrekey {
+    pub id: u32,
+    #[serde(with = "serde_bytes")]
+    pub key: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+// Public keys a user publishes so others can establish encrypted sessions;
+// the server never sees private keys
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBundle {
+    pub user_id: String,
+    #[serde(with = "serde_bytes")]
+    pub identity_key: Vec<u8>,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekeys: Vec<Prekey>,
+    pub updated_at: i64,
+}
+
+// What another user receives: at most one one-time prekey, which is
+// never handed out again
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrekeyBundle {
+    pub user_id: String,
+    #[serde(with = "serde_bytes")]
+    pub identity_key: Vec<u8>,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekey: Option<Prekey>,
+}
+
+fn valid_key(key: &[u8]) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_SIZE
+}
+
+impl KeyBundle {
+    pub async fn publish(
+        user_id: &String,
+        identity_key: Vec<u8>,
+        signed_prekey: SignedPrekey,
+        one_time_prekeys: Vec<Prekey>,
+    ) -> Result<KeyBundle> {
+        if !valid_key(&identity_key)
+            || !valid_key(&signed_prekey.key)
+            || !valid_key(&signed_prekey.signature)
+            || one_time_prekeys.len() > MAX_ONE_TIME_PREKEYS
+            || one_time_prekeys.iter().any(|p| !valid_key(&p.key))
+        {
+            return Err(Error::InvalidKeyBundle);
+        }
+        let bundle = KeyBundle {
+            user_id: user_id.clone(),
+            identity_key,
+            signed_prekey,
+            one_time_prekeys,
+            updated_at: chrono::Utc::now().timestamp_millis(),
+        };
+        let database = super::get_database();
+        database
+            .collection::<KeyBundle>("key_bundles")
+            .replace_one(doc! { "userId": user_id }, bundle.clone())
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await?;
+        Ok(bundle)
+    }
+
+    pub async fn add_prekeys(user_id: &String, prekeys: Vec<Prekey>) -> Result<()> {
+        if prekeys.len() > MAX_ONE_TIME_PREKEYS || prekeys.iter().any(|p| !valid_key(&p.key)) {
+            return Err(Error::InvalidKeyBundle);
+        }
+        let database = super::get_database();
+        let result = database
+            .collection::<KeyBundle>("key_bundles")
+            .update_one(
+                doc! { "userId": user_id },
+                doc! {
+                    "$push": {
+                        "oneTimePrekeys": {
+                            "$each": bson::to_bson(&prekeys)?,
+                            "$slice": -(MAX_ONE_TIME_PREKEYS as i32),
+                        },
+                    },
+                },
+            )
+            .await?;
+        if result.matched_count == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    pub async fn get(user_id: &String) -> Result<KeyBundle> {
+        let database = super::get_database();
+        let bundle = database
+            .collection::<KeyBundle>("key_bundles")
+            .find_one(doc! { "userId": user_id })
+            .await?;
+        bundle.ok_or(Error::NotFound)
+    }
+
+    pub async fn claim(user_id: &String) -> Result<PrekeyBundle> {
+        let database = super::get_database();
+        let bundle = database
+            .collection::<KeyBundle>("key_bundles")
+            .find_one_and_update(
+                doc! { "userId": user_id },
+                doc! { "$pop": { "oneTimePrekeys": -1 } },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::Before)
+                    .build(),
+            )
+            .await?;
+        let bundle = bundle.ok_or(Error::NotFound)?;
+        Ok(PrekeyBundle {
+            user_id: bundle.user_id,
+            identity_key: bundle.identity_key,
+            signed_prekey: bundle.signed_prekey,
+            one_time_prekey: bundle.one_time_prekeys.into_iter().next(),
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use futures_util::TryStreamExt;
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
 
@@ -9,7 +10,7 @@ use crate::{
 };
 
 use super::{
-    channels::{Channel, EntityType},
+    channels::{Channel, EntityType, PermissionOverride},
     roles::Role,
     spaces::Space,
 };
@@ -24,31 +25,8 @@ pub struct Member {
 impl Member {
     pub async fn get_permissions(&self) -> Result<PermissionSet> {
         let space = Space::get(&self.space_id).await?;
-        if space.owner == self.id {
-            Ok(PermissionSet::all())
-        } else {
-            let mut calculated_permissions = PermissionSet::from(space.base_permissions);
-            let roles_sorted = self.roles.clone();
-            let futures = roles_sorted.iter().map(Role::get);
-            let mut roles = futures_util::future::try_join_all(futures).await?;
-            roles.sort_by(|a, b| a.position.cmp(&b.position));
-            roles.reverse();
-            let default = calculated_permissions.to_vec();
-            for role in roles {
-                let role_permissions: PermissionSet = role.permissions.into();
-                if role_permissions.has_permission(Permission::Administrator) {
-                    calculated_permissions = PermissionSet::all();
-                    break;
-                }
-                for permission in &default {
-                    if !role_permissions.has_permission(*permission) {
-                        calculated_permissions.remove_permission(*permission);
-                    }
-                }
-                calculated_permissions.combine(role_permissions);
-            }
-            Ok(calculated_permissions)
-        }
+        let roles = self.get_roles().await?;
+        Ok(self.calculate_permissions(&space, &roles))
     }
 
     // PermissionOverrideState (0, 1, 2)
@@ -58,60 +36,99 @@ impl Member {
         permission: Permission,
     ) -> Result<bool> {
         let space = Space::get(&self.space_id).await?;
+        let roles = self.get_roles().await?;
+        let overrides = channel.permission_overrides().await?;
+        Ok(self.calculate_permission_in_channel(&space, &roles, overrides.as_deref(), permission))
+    }
+
+    async fn get_roles(&self) -> Result<HashMap<String, Role>> {
+        let futures = self.roles.iter().map(Role::get);
+        let roles = futures_util::future::try_join_all(futures).await?;
+        Ok(roles.into_iter().map(|r| (r.id.clone(), r)).collect())
+    }
+
+    // The calculations below work on data loaded up front, so that many
+    // members can be checked without querying the database for each one
+    pub fn calculate_permissions(
+        &self,
+        space: &Space,
+        roles: &HashMap<String, Role>,
+    ) -> PermissionSet {
         if space.owner == self.id {
-            return Ok(true);
+            return PermissionSet::all();
         }
-        let mut has_permission = false;
-        // TODO: Do we really need to order the roles?
-        let permissions = self.get_permissions().await?;
-        if permissions.has_permission(permission) {
-            has_permission = true;
-        }
-        match channel {
-            Channel::InformationChannel { permissions, .. }
-            | Channel::AnnouncementChannel { permissions, .. }
-            | Channel::ChatChannel { permissions, .. } => {
-                let mut role_overrides = permissions
-                    .iter()
-                    .filter(|p| p.entity_type == EntityType::Role)
-                    .filter(|p| self.roles.contains(&p.id))
-                    .collect::<Vec<_>>();
-                let mut map = HashMap::new();
-                for role in &role_overrides {
-                    map.insert(role.id.clone(), Role::get(&role.id).await?);
-                }
-                role_overrides.sort_by(|a, b| {
-                    map.get(&a.id)
-                        .unwrap()
-                        .position
-                        .cmp(&map.get(&b.id).unwrap().position)
-                });
-                role_overrides.reverse();
-                for role_override in role_overrides {
-                    if role_override.allow.has_permission(permission) {
-                        has_permission = true;
-                    }
-                    if role_override.deny.has_permission(permission) {
-                        has_permission = false;
-                    }
+        let mut calculated_permissions = PermissionSet::from(space.base_permissions);
+        let mut member_roles = self
+            .roles
+            .iter()
+            .filter_map(|id| roles.get(id))
+            .collect::<Vec<_>>();
+        member_roles.sort_by(|a, b| a.position.cmp(&b.position));
+        member_roles.reverse();
+        let default = calculated_permissions.to_vec();
+        for role in member_roles {
+            let role_permissions: PermissionSet = role.permissions.into();
+            if role_permissions.has_permission(Permission::Administrator) {
+                calculated_permissions = PermissionSet::all();
+                break;
+            }
+            for permission in &default {
+                if !role_permissions.has_permission(*permission) {
+                    calculated_permissions.remove_permission(*permission);
                 }
+            }
+            calculated_permissions.combine(role_permissions);
+        }
+        calculated_permissions
+    }
 
-                let member_override = permissions
-                    .iter()
-                    .find(|p| p.id == self.id && p.entity_type == EntityType::Member);
-                if let Some(member_override) = member_override {
-                    if member_override.allow.has_permission(permission) {
-                        has_permission = true;
-                    }
-                    if member_override.deny.has_permission(permission) {
-                        has_permission = false;
-                    }
-                }
+    pub fn calculate_permission_in_channel(
+        &self,
+        space: &Space,
+        roles: &HashMap<String, Role>,
+        overrides: Option<&[PermissionOverride]>,
+        permission: Permission,
+    ) -> bool {
+        if space.owner == self.id {
+            return true;
+        }
+        // TODO: Do we really need to order the roles?
+        let mut has_permission = self
+            .calculate_permissions(space, roles)
+            .has_permission(permission);
+        let Some(permissions) = overrides else {
+            return false; // FIXME: Need to handle private channels
+        };
+        let mut role_overrides = permissions
+            .iter()
+            .filter(|p| p.entity_type == EntityType::Role)
+            .filter(|p| self.roles.contains(&p.id))
+            .filter_map(|p| roles.get(&p.id).map(|role| (role.position, p)))
+            .collect::<Vec<_>>();
+        role_overrides.sort_by_key(|(position, _)| *position);
+        role_overrides.reverse();
+        for (_, role_override) in role_overrides {
+            if role_override.allow.has_permission(permission) {
+                has_permission = true;
+            }
+            if role_override.deny.has_permission(permission) {
+                has_permission = false;
+            }
+        }
 
-                Ok(has_permission)
+        let member_override = permissions
+            .iter()
+            .find(|p| p.id == self.id && p.entity_type == EntityType::Member);
+        if let Some(member_override) = member_override {
+            if member_override.allow.has_permission(permission) {
+                has_permission = true;
+            }
+            if member_override.deny.has_permission(permission) {
+                has_permission = false;
             }
-            _ => Ok(false), // FIXME: Need to handle private channels
         }
+
+        has_permission
     }
 
     pub async fn is_owner(&self) -> Result<bool> {
@@ -119,6 +136,20 @@ impl Member {
         Ok(space.owner == self.id)
     }
 
+    pub async fn create(id: &str, space_id: &str, roles: Vec<String>) -> Result<Member> {
+        let database = super::get_database();
+        let member = Member {
+            id: id.to_owned(),
+            space_id: space_id.to_owned(),
+            roles,
+        };
+        database
+            .collection::<Member>("members")
+            .insert_one(member.clone())
+            .await?;
+        Ok(member)
+    }
+
     pub async fn get(id: &String, space_id: &String) -> Result<Member> {
         let database = super::get_database();
         let member = database
@@ -132,6 +163,43 @@ impl Member {
         Ok(member)
     }
 
+    pub async fn get_with_roles(space_id: &String, roles: &[String]) -> Result<Vec<Member>> {
+        let database = super::get_database();
+        let members = database
+            .collection::<Member>("members")
+            .find(doc! {
+                "space_id": space_id,
+                "roles": { "$in": roles },
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(members)
+    }
+
+    pub async fn get_all(space_id: &String) -> Result<Vec<Member>> {
+        let database = super::get_database();
+        let members = database
+            .collection::<Member>("members")
+            .find(doc! { "space_id": space_id })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(members)
+    }
+
+    // Space members without a member document only have the base permissions
+    pub async fn get_or_default(id: &String, space_id: &String) -> Result<Member> {
+        match Member::get(id, space_id).await {
+            Err(crate::errors::Error::NotFound) => Ok(Member {
+                id: id.clone(),
+                space_id: space_id.clone(),
+                roles: Vec::new(),
+            }),
+            member => member,
+        }
+    }
+
     pub async fn delete(&self, space_id: &String) -> Result<()> {
         let database = super::get_database();
         database
@@ -1,67 +1,664 @@
-use mongodb::bson::doc;
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
-use crate::errors::{Error, Result};
+use crate::{
+    errors::{Error, Result},
+    globals::MAX_BULK_DELETE,
+    services::{environment::MAX_PIN_COUNT, markdown::ContentNode},
+};
+
+use super::{
+    attachments::{Attachment, MessageAttachment},
+    embeds::{Embed, EmbedKind},
+    emojis::MessageEmoji,
+    reactions::{Reaction, ReactionCount},
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Message {
     pub(crate) id: String,
     pub(crate) content: String,
+    // Parsed form of the content, see services::markdown
+    #[serde(default)]
+    pub(crate) ast: Vec<ContentNode>,
     pub(crate) author_id: String,
     pub(crate) created_at: i64,
     pub(crate) edited: bool,
     pub(crate) edited_at: Option<i64>,
     pub(crate) channel_id: String,
+    pub(crate) reply_to: Option<MessageReference>,
+    pub(crate) thread_id: Option<String>,
+    #[serde(default)]
+    pub(crate) reactions: Vec<ReactionCount>,
+    #[serde(default)]
+    pub(crate) emojis: Vec<MessageEmoji>,
+    #[serde(default)]
+    pub(crate) mentions: Mentions,
+    #[serde(default)]
+    pub(crate) attachments: Vec<MessageAttachment>,
+    #[serde(default)]
+    pub(crate) embeds: Vec<Embed>,
+    #[serde(default)]
+    pub(crate) pinned: bool,
+    pub(crate) pinned_at: Option<i64>,
+    pub(crate) system: Option<SystemMessage>,
+    // Left out when unset, which keeps the sparse index small
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<i64>,
+    pub(crate) encrypted: Option<EncryptedContent>,
+}
+
+// Opaque to the server; the content key is wrapped once per recipient
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedContent {
+    #[serde(with = "serde_bytes")]
+    pub(crate) ciphertext: Vec<u8>,
+    pub(crate) envelopes: Vec<KeyEnvelope>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyEnvelope {
+    pub(crate) recipient_id: String,
+    #[serde(with = "serde_bytes")]
+    pub(crate) key: Vec<u8>,
+}
+
+// Everything needed to send a message, kept as-is for scheduled messages
+// so that it is validated again when it is actually sent
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageDraft {
+    pub(crate) channel_id: String,
+    pub(crate) content: String,
+    pub(crate) reply_to: Option<String>,
+    pub(crate) attachments: Vec<String>,
+    pub(crate) embeds: Vec<Embed>,
+    // Seconds after sending until the message deletes itself
+    pub(crate) expires_after: Option<i64>,
+    pub(crate) encrypted: Option<EncryptedContent>,
+    // Echoed back to clients so they can reconcile optimistic sends
+    #[serde(default)]
+    pub(crate) nonce: Option<String>,
+}
+
+// Generated by the server when something happens in a channel
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SystemMessage {
+    #[serde(rename_all = "camelCase")]
+    MessagePinned {
+        message_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    MessageUnpinned {
+        message_id: String,
+    },
+    EncryptionEnabled,
+    #[serde(rename_all = "camelCase")]
+    MemberAdded {
+        user_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    MemberRemoved {
+        user_id: String,
+    },
+    MemberLeft,
+    #[serde(rename_all = "camelCase")]
+    OwnershipTransferred {
+        user_id: String,
+    },
+}
+
+// A snapshot of the replied-to message, so clients can render it
+// without fetching the original
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReference {
+    pub(crate) id: String,
+    pub(crate) author_id: String,
+    pub(crate) snippet: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mentions {
+    pub(crate) users: Vec<String>,
+    pub(crate) roles: Vec<String>,
+    pub(crate) channels: Vec<String>,
+    pub(crate) everyone: bool,
+    pub(crate) here: bool,
+}
+
+impl Mentions {
+    pub fn is_mass_mention(&self) -> bool {
+        self.everyone || self.here
+    }
+}
+
+// Channels must already be filtered down to those the caller can view
+#[derive(Clone, Debug, Default)]
+pub struct MessageSearch {
+    pub text: String,
+    pub channel_ids: Vec<String>,
+    pub author_id: Option<String>,
+    pub mentions: Option<String>,
+    pub has_attachment: Option<bool>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub before: Option<String>,
+    pub limit: i64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BulkDeleteFilter {
+    pub message_ids: Option<Vec<String>>,
+    pub author_id: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub last: Option<i64>,
+}
+
+impl From<&Message> for MessageReference {
+    fn from(message: &Message) -> Self {
+        MessageReference {
+            id: message.id.clone(),
+            author_id: message.author_id.clone(),
+            snippet: message.content.chars().take(100).collect(),
+        }
+    }
 }
 
 impl Message {
-    pub async fn create(channel_id: String, author_id: String, content: String) -> Result<Message> {
-        let message = Message {
+    pub fn new(channel_id: String, author_id: String, content: String) -> Message {
+        Message {
             id: Ulid::new().to_string(),
             content,
+            ast: Vec::new(),
             author_id,
             created_at: chrono::Utc::now().timestamp_millis(),
             edited: false,
             edited_at: None,
             channel_id,
-        };
+            reply_to: None,
+            thread_id: None,
+            reactions: Vec::new(),
+            emojis: Vec::new(),
+            mentions: Mentions::default(),
+            attachments: Vec::new(),
+            embeds: Vec::new(),
+            pinned: false,
+            pinned_at: None,
+            system: None,
+            expires_at: None,
+            encrypted: None,
+        }
+    }
+
+    pub fn new_system(channel_id: String, author_id: String, system: SystemMessage) -> Message {
+        let mut message = Message::new(channel_id, author_id, String::new());
+        message.system = Some(system);
+        message
+    }
+
+    pub async fn create(&self) -> Result<()> {
         let database = super::get_database();
         database
             .collection::<Message>("messages")
-            .insert_one(message.clone())
+            .insert_one(self.clone())
             .await?;
-        Ok(message)
+        Ok(())
     }
-    pub async fn edit(&self, content: String) -> Result<Message> {
+
+    pub async fn get(id: &String) -> Result<Message> {
         let database = super::get_database();
         let message = database
+            .collection::<Message>("messages")
+            .find_one(doc! { "id": id })
+            .await?;
+        match message {
+            Some(message) => Ok(message),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    pub async fn exists(id: &String) -> Result<bool> {
+        let database = super::get_database();
+        let count = database
+            .collection::<Message>("messages")
+            .count_documents(doc! { "id": id })
+            .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn edit(
+        &self,
+        content: String,
+        ast: Vec<ContentNode>,
+        emojis: Vec<MessageEmoji>,
+        mentions: Mentions,
+        encrypted: Option<EncryptedContent>,
+    ) -> Result<Message> {
+        let database = super::get_database();
+        let edited_at = chrono::Utc::now().timestamp_millis();
+        // Links are unfurled again for the new content
+        let rich_embeds = self
+            .embeds
+            .iter()
+            .filter(|e| e.kind == EmbedKind::Rich)
+            .cloned()
+            .collect::<Vec<_>>();
+        // The revision is taken from the document as it was replaced, so
+        // that concurrent edits each record the content they overwrote
+        let previous = database
             .collection::<Message>("messages")
             .find_one_and_update(
                 doc! { "id": &self.id },
                 doc! { "$set": {
-                    "content": content,
+                    "content": &content,
+                    "ast": bson::to_bson(&ast)?,
+                    "embeds": bson::to_bson(&rich_embeds)?,
+                    "emojis": bson::to_bson(&emojis)?,
+                    "mentions": bson::to_bson(&mentions)?,
+                    "encrypted": bson::to_bson(&encrypted)?,
                     "edited": true,
-                    "editedAt": chrono::Utc::now().timestamp_millis(),
+                    "editedAt": edited_at,
                 } },
             )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::Before)
+                    .build(),
+            )
             .await?;
-        match message {
-            Some(message) => Ok(message),
-            None => Err(Error::NotFound),
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Err(Error::NotFound),
+        };
+        // Keep the previous content around for moderators
+        database
+            .collection::<MessageRevision>("message_revisions")
+            .insert_one(previous.revision(edited_at, false))
+            .await?;
+        let mut message = previous;
+        message.content = content;
+        message.ast = ast;
+        message.embeds = rich_embeds;
+        message.emojis = emojis;
+        message.mentions = mentions;
+        message.encrypted = encrypted;
+        message.edited = true;
+        message.edited_at = Some(edited_at);
+        Ok(message)
+    }
+
+    // Replaces the unfurled embeds, unless the content changed since they were fetched
+    pub async fn set_link_embeds(&self, embeds: Vec<Embed>) -> Result<Option<Message>> {
+        let database = super::get_database();
+        let mut all = self
+            .embeds
+            .iter()
+            .filter(|e| e.kind == EmbedKind::Rich)
+            .cloned()
+            .collect::<Vec<_>>();
+        all.extend(embeds);
+        let message = database
+            .collection::<Message>("messages")
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "content": &self.content,
+                },
+                doc! { "$set": {
+                    "embeds": bson::to_bson(&all)?,
+                } },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        Ok(message)
+    }
+
+    fn revision(&self, replaced_at: i64, deleted: bool) -> MessageRevision {
+        MessageRevision {
+            id: Ulid::new().to_string(),
+            message_id: self.id.clone(),
+            channel_id: self.channel_id.clone(),
+            content: self.content.clone(),
+            encrypted: self.encrypted.clone(),
+            created_at: self.edited_at.unwrap_or(self.created_at),
+            replaced_at,
+            deleted,
         }
     }
 
-    pub async fn delete(&self) -> Result<Message> {
+    pub async fn get_expired(now: i64) -> Result<Vec<Message>> {
+        let database = super::get_database();
+        let messages = database
+            .collection::<Message>("messages")
+            .find(doc! { "expiresAt": { "$lte": now } })
+            .with_options(FindOptions::builder().limit(100).build())
+            .await?
+            .try_collect()
+            .await?;
+        Ok(messages)
+    }
+
+    pub async fn pin(&self) -> Result<Message> {
+        let database = super::get_database();
+        let messages = database.collection::<Message>("messages");
+        let message = messages
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "pinned": { "$ne": true },
+                },
+                doc! { "$set": {
+                    "pinned": true,
+                    "pinnedAt": chrono::Utc::now().timestamp_millis(),
+                } },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(Error::AlreadyExists)?;
+        // Counted after pinning, so that concurrent pins cannot both slip in
+        // under the limit
+        let count = messages
+            .count_documents(doc! {
+                "channelId": &self.channel_id,
+                "pinned": true,
+            })
+            .await?;
+        if count > *MAX_PIN_COUNT {
+            message.unpin().await?;
+            return Err(Error::PinLimitReached);
+        }
+        Ok(message)
+    }
+
+    pub async fn unpin(&self) -> Result<Message> {
+        let database = super::get_database();
+        let message = database
+            .collection::<Message>("messages")
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "pinned": true,
+                },
+                doc! { "$set": {
+                    "pinned": false,
+                    "pinnedAt": null,
+                } },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        message.ok_or(Error::NotFound)
+    }
+
+    pub async fn get_pinned(channel_id: &String) -> Result<Vec<Message>> {
+        let database = super::get_database();
+        let messages = database
+            .collection::<Message>("messages")
+            .find(doc! {
+                "channelId": channel_id,
+                "pinned": true,
+            })
+            .with_options(FindOptions::builder().sort(doc! { "pinnedAt": -1 }).build())
+            .await?
+            .try_collect()
+            .await?;
+        Ok(messages)
+    }
+
+    // Newest first, paginated with the ID of the last message seen
+    pub async fn search(search: &MessageSearch) -> Result<Vec<Message>> {
+        let database = super::get_database();
+        let mut query = doc! {
+            "$text": { "$search": &search.text },
+            "channelId": { "$in": &search.channel_ids },
+            "system": null,
+        };
+        if let Some(author_id) = &search.author_id {
+            query.insert("authorId", author_id);
+        }
+        if let Some(user_id) = &search.mentions {
+            query.insert("mentions.users", user_id);
+        }
+        match search.has_attachment {
+            Some(true) => {
+                query.insert("attachments.0", doc! { "$exists": true });
+            }
+            Some(false) => {
+                query.insert("attachments.0", doc! { "$exists": false });
+            }
+            None => {}
+        }
+        let mut created_at = doc! {};
+        if let Some(since) = search.since {
+            created_at.insert("$gte", since);
+        }
+        if let Some(until) = search.until {
+            created_at.insert("$lt", until);
+        }
+        if !created_at.is_empty() {
+            query.insert("createdAt", created_at);
+        }
+        if let Some(before) = &search.before {
+            query.insert("id", doc! { "$lt": before });
+        }
+        let messages = database
+            .collection::<Message>("messages")
+            .find(query)
+            .with_options(
+                FindOptions::builder()
+                    .sort(doc! { "id": -1 })
+                    .limit(search.limit)
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        Ok(messages)
+    }
+
+    // Returns the IDs of the deleted messages
+    pub async fn bulk_delete(
+        channel_id: &String,
+        filter: &BulkDeleteFilter,
+    ) -> Result<Vec<String>> {
+        let database = super::get_database();
+        let mut query = doc! { "channelId": channel_id };
+        if let Some(message_ids) = &filter.message_ids {
+            query.insert("id", doc! { "$in": message_ids });
+        }
+        if let Some(author_id) = &filter.author_id {
+            query.insert("authorId", author_id);
+        }
+        let mut created_at = doc! {};
+        if let Some(since) = filter.since {
+            created_at.insert("$gte", since);
+        }
+        if let Some(until) = filter.until {
+            created_at.insert("$lt", until);
+        }
+        if !created_at.is_empty() {
+            query.insert("createdAt", created_at);
+        }
+        let limit = filter
+            .last
+            .unwrap_or(*MAX_BULK_DELETE)
+            .clamp(1, *MAX_BULK_DELETE);
+        let messages: Vec<Message> = database
+            .collection::<Message>("messages")
+            .find(query)
+            .with_options(
+                FindOptions::builder()
+                    .sort(doc! { "id": -1 })
+                    .limit(limit)
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let message_ids = messages.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        Message::delete_by_ids(&message_ids).await?;
+        // The deleted content stays in the revision history for moderators,
+        // written only once the messages are gone, the same as a single delete
+        let deleted_at = chrono::Utc::now().timestamp_millis();
+        let revisions = messages
+            .iter()
+            .filter(|m| m.system.is_none())
+            .map(|m| m.revision(deleted_at, true))
+            .collect::<Vec<_>>();
+        if !revisions.is_empty() {
+            database
+                .collection::<MessageRevision>("message_revisions")
+                .insert_many(revisions)
+                .await?;
+        }
+        Ok(message_ids)
+    }
+
+    // Used when channels are removed, in batches to keep each query small
+    pub async fn delete_in_channels(channel_ids: &[String]) -> Result<()> {
+        let database = super::get_database();
+        loop {
+            let message_ids: Vec<String> = database
+                .collection::<Document>("messages")
+                .find(doc! { "channelId": { "$in": channel_ids } })
+                .with_options(
+                    FindOptions::builder()
+                        .limit(*MAX_BULK_DELETE)
+                        .projection(doc! { "id": 1 })
+                        .build(),
+                )
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+                .into_iter()
+                .filter_map(|m| m.get_str("id").ok().map(str::to_owned))
+                .collect();
+            if message_ids.is_empty() {
+                return Ok(());
+            }
+            Message::delete_by_ids(&message_ids).await?;
+            // Revisions are only kept for as long as their channel exists
+            database
+                .collection::<MessageRevision>("message_revisions")
+                .delete_many(doc! { "messageId": { "$in": &message_ids } })
+                .await?;
+        }
+    }
+
+    async fn delete_by_ids(message_ids: &[String]) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<Message>("messages")
+            .delete_many(doc! { "id": { "$in": message_ids } })
+            .await?;
+        database
+            .collection::<Reaction>("reactions")
+            .delete_many(doc! { "messageId": { "$in": message_ids } })
+            .await?;
+        Attachment::delete_for_messages(message_ids).await?;
+        Ok(())
+    }
+
+    pub async fn get_revisions(&self) -> Result<Vec<MessageRevision>> {
+        MessageRevision::get_all(&self.id).await
+    }
+
+    async fn remove(&self) -> Result<Message> {
         let database = super::get_database();
         let message = database
             .collection::<Message>("messages")
             .find_one_and_delete(doc! { "id": &self.id })
             .await?;
-        match message {
-            Some(message) => Ok(message),
-            None => Err(Error::NotFound),
+        let message = match message {
+            Some(message) => message,
+            None => return Err(Error::NotFound),
+        };
+        database
+            .collection::<Reaction>("reactions")
+            .delete_many(doc! { "messageId": &self.id })
+            .await?;
+        Attachment::delete_for_messages(std::slice::from_ref(&self.id)).await?;
+        Ok(message)
+    }
+
+    // The revision history is kept, ending with the deleted content
+    pub async fn delete(&self) -> Result<Message> {
+        let message = self.remove().await?;
+        if message.system.is_none() {
+            let database = super::get_database();
+            database
+                .collection::<MessageRevision>("message_revisions")
+                .insert_one(message.revision(chrono::Utc::now().timestamp_millis(), true))
+                .await?;
         }
+        Ok(message)
+    }
+
+    // Self-destructing messages leave nothing behind, not even revisions
+    pub async fn purge(&self) -> Result<Message> {
+        let message = self.remove().await?;
+        let database = super::get_database();
+        database
+            .collection::<MessageRevision>("message_revisions")
+            .delete_many(doc! { "messageId": &self.id })
+            .await?;
+        Ok(message)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRevision {
+    pub(crate) id: String,
+    pub(crate) message_id: String,
+    #[serde(default)]
+    pub(crate) channel_id: String,
+    pub(crate) content: String,
+    pub(crate) encrypted: Option<EncryptedContent>,
+    pub(crate) created_at: i64,
+    pub(crate) replaced_at: i64,
+    // Set on the last revision of a deleted message
+    #[serde(default)]
+    pub(crate) deleted: bool,
+}
+
+impl MessageRevision {
+    // Also works for deleted messages, whose revisions outlive them
+    pub async fn get_all(message_id: &String) -> Result<Vec<MessageRevision>> {
+        let database = super::get_database();
+        let revisions = database
+            .collection::<MessageRevision>("message_revisions")
+            .find(doc! { "messageId": message_id })
+            .with_options(FindOptions::builder().sort(doc! { "id": 1 }).build())
+            .await?
+            .try_collect()
+            .await?;
+        Ok(revisions)
     }
 }
@@ -1,19 +1,33 @@
+pub mod attachments;
 pub mod calls;
 pub mod channels;
+pub mod embeds;
 pub mod emojis;
 pub mod events;
 pub mod infractions;
 pub mod invites;
+pub mod keys;
 pub mod members;
 pub mod messages;
+pub mod reactions;
+pub mod read_states;
 pub mod roles;
+pub mod scheduled_messages;
 pub mod scopes;
 pub mod spaces;
 pub mod users;
 
-use crate::services::environment::{MONGODB_DATABASE, MONGODB_URI};
+use crate::{
+    errors::Result,
+    services::environment::{MONGODB_DATABASE, MONGODB_URI},
+};
 
-use mongodb::{Client, Database};
+use mongodb::{
+    bson::doc,
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    Client, Database, IndexModel,
+};
 use once_cell::sync::OnceCell;
 
 static DATABASE: OnceCell<Client> = OnceCell::new();
@@ -22,9 +36,23 @@ pub async fn connect() {
     let client = Client::with_uri_str(&*MONGODB_URI)
         .await
         .expect("Failed to connect to MongoDB");
+    check_transactions(&client).await;
     DATABASE.set(client).expect("Failed to set MongoDB client");
 }
 
+// Some updates span several documents and run in a transaction, which a
+// standalone server does not support
+async fn check_transactions(client: &Client) {
+    let hello = client
+        .database("admin")
+        .run_command(doc! { "hello": 1 })
+        .await
+        .expect("Failed to query MongoDB");
+    if !hello.contains_key("setName") && hello.get_str("msg") != Ok("isdbgrid") {
+        panic!("MongoDB must run as a replica set or sharded cluster");
+    }
+}
+
 pub fn get_connection() -> &'static Client {
     DATABASE.get().expect("Failed to get MongoDB client")
 }
@@ -32,3 +60,73 @@ pub fn get_connection() -> &'static Client {
 pub fn get_database() -> Database {
     get_connection().database(&MONGODB_DATABASE)
 }
+
+pub async fn create_indexes() -> Result<()> {
+    let database = get_database();
+    database
+        .collection::<messages::Message>("messages")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "content": "text" })
+                .build(),
+        )
+        .await?;
+    database
+        .collection::<reactions::Reaction>("reactions")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "messageId": 1, "userId": 1, "key": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await?;
+    database
+        .collection::<emojis::Emoji>("emojis")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "space_id": 1, "name": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await?;
+    database
+        .collection::<attachments::Attachment>("attachments")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "messageId": 1, "createdAt": 1 })
+                .build(),
+        )
+        .await?;
+    // Polled every second by the message scheduler
+    database
+        .collection::<messages::Message>("messages")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "expiresAt": 1 })
+                .options(IndexOptions::builder().sparse(true).build())
+                .build(),
+        )
+        .await?;
+    database
+        .collection::<channels::Channel>("channels")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "pair_key": 1 })
+                .options(IndexOptions::builder().unique(true).sparse(true).build())
+                .build(),
+        )
+        .await?;
+    database
+        .collection::<scheduled_messages::ScheduledMessage>("scheduled_messages")
+        .create_index(IndexModel::builder().keys(doc! { "sendAt": 1 }).build())
+        .await?;
+    Ok(())
+}
+
+// Unique indexes turn concurrent duplicate inserts into this error
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        *error.kind,
+        ErrorKind::Write(WriteFailure::WriteError(ref e)) if e.code == 11000
+    )
+}
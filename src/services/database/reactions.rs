@@ -0,0 +1,192 @@
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{self, doc},
+    options::FindOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+
+use super::messages::Message;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReactionEmoji {
+    Unicode { value: String },
+    Custom { id: String },
+}
+
+impl ReactionEmoji {
+    // Used to match reactions without comparing subdocuments
+    pub fn key(&self) -> String {
+        match self {
+            ReactionEmoji::Unicode { value } => format!("unicode:{value}"),
+            ReactionEmoji::Custom { id } => format!("custom:{id}"),
+        }
+    }
+}
+
+// Aggregated on the message
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionCount {
+    pub(crate) key: String,
+    pub(crate) emoji: ReactionEmoji,
+    pub(crate) count: i32,
+}
+
+// One per user, emoji and message
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reaction {
+    pub(crate) message_id: String,
+    pub(crate) user_id: String,
+    pub(crate) key: String,
+    pub(crate) created_at: i64,
+}
+
+impl Reaction {
+    pub async fn add(message: &Message, user_id: &str, emoji: &ReactionEmoji) -> Result<()> {
+        let database = super::get_database();
+        let reactions = database.collection::<Reaction>("reactions");
+        let key = emoji.key();
+        // The unique index rejects the same reaction added twice at once
+        reactions
+            .insert_one(Reaction {
+                message_id: message.id.clone(),
+                user_id: user_id.to_owned(),
+                key: key.clone(),
+                created_at: chrono::Utc::now().timestamp_millis(),
+            })
+            .await
+            .map_err(|e| match super::is_duplicate_key(&e) {
+                true => Error::AlreadyExists,
+                false => e.into(),
+            })?;
+        let messages = database.collection::<Message>("messages");
+        let incremented = messages
+            .update_one(
+                doc! {
+                    "id": &message.id,
+                    "reactions.key": &key,
+                },
+                doc! {
+                    "$inc": {
+                        "reactions.$.count": 1,
+                    },
+                },
+            )
+            .await?;
+        if incremented.matched_count == 0 {
+            let count = ReactionCount {
+                key: key.clone(),
+                emoji: emoji.clone(),
+                count: 1,
+            };
+            let pushed = messages
+                .update_one(
+                    doc! {
+                        "id": &message.id,
+                        "reactions.key": { "$ne": &key },
+                    },
+                    doc! {
+                        "$push": {
+                            "reactions": bson::to_bson(&count)?,
+                        },
+                    },
+                )
+                .await?;
+            // Someone else reacted with the same emoji in the meantime
+            if pushed.matched_count == 0 {
+                messages
+                    .update_one(
+                        doc! {
+                            "id": &message.id,
+                            "reactions.key": &key,
+                        },
+                        doc! {
+                            "$inc": {
+                                "reactions.$.count": 1,
+                            },
+                        },
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove(message: &Message, user_id: &String, emoji: &ReactionEmoji) -> Result<()> {
+        let database = super::get_database();
+        let key = emoji.key();
+        let deleted = database
+            .collection::<Reaction>("reactions")
+            .delete_one(doc! {
+                "messageId": &message.id,
+                "userId": user_id,
+                "key": &key,
+            })
+            .await?;
+        if deleted.deleted_count == 0 {
+            return Err(Error::NotFound);
+        }
+        let messages = database.collection::<Message>("messages");
+        messages
+            .update_one(
+                doc! {
+                    "id": &message.id,
+                    "reactions.key": &key,
+                },
+                doc! {
+                    "$inc": {
+                        "reactions.$.count": -1,
+                    },
+                },
+            )
+            .await?;
+        messages
+            .update_one(
+                doc! {
+                    "id": &message.id,
+                },
+                doc! {
+                    "$pull": {
+                        "reactions": {
+                            "count": { "$lte": 0 },
+                        },
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_users(
+        message: &Message,
+        emoji: &ReactionEmoji,
+        limit: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Vec<String>> {
+        let database = super::get_database();
+        let mut query = doc! {
+            "messageId": &message.id,
+            "key": emoji.key(),
+        };
+        if let Some(after) = after {
+            query.insert("userId", doc! { "$gt": after });
+        }
+        let reactions: Vec<Reaction> = database
+            .collection::<Reaction>("reactions")
+            .find(query)
+            .with_options(
+                FindOptions::builder()
+                    .sort(doc! { "userId": 1 })
+                    .limit(limit.unwrap_or(100).min(100))
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        Ok(reactions.into_iter().map(|r| r.user_id).collect())
+    }
+}
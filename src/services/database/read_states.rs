@@ -0,0 +1,98 @@
+use mongodb::{bson::doc, options::UpdateOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Result;
+
+use super::messages::Message;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadState {
+    pub user_id: String,
+    pub channel_id: String,
+    pub last_message_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelUnread {
+    pub channel_id: String,
+    pub last_message_id: Option<String>,
+    pub unread_count: u64,
+    pub mention_count: u64,
+}
+
+impl ReadState {
+    pub async fn get(user_id: &String, channel_id: &String) -> Result<Option<ReadState>> {
+        let database = super::get_database();
+        let read_state = database
+            .collection::<ReadState>("read_states")
+            .find_one(doc! {
+                "userId": user_id,
+                "channelId": channel_id,
+            })
+            .await?;
+        Ok(read_state)
+    }
+
+    // Message IDs are ULIDs, so the read marker only ever moves forward
+    pub async fn ack(user_id: &String, channel_id: &String, message_id: &String) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<ReadState>("read_states")
+            .update_one(
+                doc! {
+                    "userId": user_id,
+                    "channelId": channel_id,
+                },
+                doc! {
+                    "$max": {
+                        "lastMessageId": message_id,
+                    },
+                },
+            )
+            .with_options(UpdateOptions::builder().upsert(true).build())
+            .await?;
+        Ok(())
+    }
+
+    // Roles are the user's roles in the channel's space, used for role mentions
+    pub async fn get_unread(
+        user_id: &String,
+        channel_id: &String,
+        roles: &[String],
+    ) -> Result<ChannelUnread> {
+        let database = super::get_database();
+        let read_state = ReadState::get(user_id, channel_id).await?;
+        let last_message_id = read_state.map(|r| r.last_message_id);
+        let mut query = doc! {
+            "channelId": channel_id,
+            "authorId": { "$ne": user_id },
+        };
+        if let Some(last_message_id) = &last_message_id {
+            query.insert("id", doc! { "$gt": last_message_id });
+        }
+        let messages = database.collection::<Message>("messages");
+        let unread_count = messages.count_documents(query.clone()).await?;
+        let mention_count = if unread_count > 0 {
+            query.insert(
+                "$or",
+                vec![
+                    doc! { "mentions.users": user_id },
+                    doc! { "mentions.roles": { "$in": roles } },
+                    doc! { "mentions.everyone": true },
+                    doc! { "mentions.here": true },
+                ],
+            );
+            messages.count_documents(query).await?
+        } else {
+            0
+        };
+        Ok(ChannelUnread {
+            channel_id: channel_id.clone(),
+            last_message_id,
+            unread_count,
+            mention_count,
+        })
+    }
+}
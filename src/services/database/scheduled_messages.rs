@@ -0,0 +1,159 @@
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    errors::{Error, Result},
+    globals::SCHEDULED_CLAIM_LEASE,
+};
+
+use super::messages::MessageDraft;
+
+// Stored in the database so that pending messages survive restarts
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub author_id: String,
+    pub send_at: i64,
+    pub draft: MessageDraft,
+    // Set by the instance sending the message; a claim that is not
+    // completed within the lease is picked up again by another instance
+    #[serde(default)]
+    pub claimed_at: Option<i64>,
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    // Chosen on the first claim, so that a retried send can tell whether
+    // the message already went out
+    #[serde(default)]
+    pub message_id: Option<String>,
+}
+
+impl ScheduledMessage {
+    pub async fn create(
+        author_id: String,
+        send_at: i64,
+        draft: MessageDraft,
+    ) -> Result<ScheduledMessage> {
+        let scheduled = ScheduledMessage {
+            id: Ulid::new().to_string(),
+            author_id,
+            send_at,
+            draft,
+            claimed_at: None,
+            claimed_by: None,
+            message_id: None,
+        };
+        let database = super::get_database();
+        database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .insert_one(scheduled.clone())
+            .await?;
+        Ok(scheduled)
+    }
+
+    pub async fn get_pending(
+        author_id: &String,
+        channel_id: Option<&String>,
+    ) -> Result<Vec<ScheduledMessage>> {
+        let database = super::get_database();
+        let mut query = doc! { "authorId": author_id };
+        if let Some(channel_id) = channel_id {
+            query.insert("draft.channelId", channel_id);
+        }
+        let scheduled = database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .find(query)
+            .with_options(FindOptions::builder().sort(doc! { "sendAt": 1 }).build())
+            .await?
+            .try_collect()
+            .await?;
+        Ok(scheduled)
+    }
+
+    pub async fn cancel(id: &String, author_id: &String) -> Result<()> {
+        let database = super::get_database();
+        let result = database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .delete_one(doc! {
+                "id": id,
+                "authorId": author_id,
+            })
+            .await?;
+        if result.deleted_count == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    // The entry is only removed once the message has been sent, so that it
+    // survives an instance going away in the middle of sending it
+    pub async fn claim_due(now: i64) -> Result<Option<ScheduledMessage>> {
+        let database = super::get_database();
+        let scheduled = database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .find_one_and_update(
+                doc! {
+                    "sendAt": { "$lte": now },
+                    "$or": [
+                        { "claimedAt": null },
+                        { "claimedAt": { "$lte": now - *SCHEDULED_CLAIM_LEASE } },
+                    ],
+                },
+                vec![doc! {
+                    "$set": {
+                        "claimedAt": now,
+                        "claimedBy": Ulid::new().to_string(),
+                        "messageId": { "$ifNull": ["$messageId", Ulid::new().to_string()] },
+                    },
+                }],
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .sort(doc! { "sendAt": 1 })
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?;
+        Ok(scheduled)
+    }
+
+    // Gives up the claim and puts the message back for a later time
+    pub async fn postpone(&self, send_at: i64) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .update_one(
+                doc! {
+                    "id": &self.id,
+                    "claimedBy": &self.claimed_by,
+                },
+                doc! {
+                    "$set": {
+                        "sendAt": send_at,
+                        "claimedAt": null,
+                        "claimedBy": null,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Does nothing if the claim has expired and been taken over meanwhile
+    pub async fn complete(&self) -> Result<()> {
+        let database = super::get_database();
+        database
+            .collection::<ScheduledMessage>("scheduled_messages")
+            .delete_one(doc! {
+                "id": &self.id,
+                "claimedBy": &self.claimed_by,
+            })
+            .await?;
+        Ok(())
+    }
+}
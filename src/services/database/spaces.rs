@@ -1,11 +1,20 @@
 use futures_util::TryStreamExt;
-use mongodb::bson::doc;
+use mongodb::{
+    bson::{self, doc},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
 use crate::errors::{Error, Result};
 
-use super::{channels::Channel, invites::Invite, members::Member, roles::Role};
+use super::{
+    channels::{CategoryMove, Channel},
+    emojis::Emoji,
+    invites::Invite,
+    members::Member,
+    roles::Role,
+};
 // use super::invites::Invite;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -70,6 +79,12 @@ impl Space {
                 "space_id": &self.id,
             })
             .await?;
+        let emojis = super::get_database().collection::<Emoji>("emojis");
+        emojis
+            .delete_many(doc! {
+                "space_id": &self.id,
+            })
+            .await?;
         let members = super::get_database().collection::<Member>("members");
         members
             .delete_many(doc! {
@@ -108,6 +123,105 @@ impl Space {
             .await?;
         Ok(())
     }
+    pub async fn add_channel(&self, id: &String) -> Result<()> {
+        let spaces = super::get_database().collection::<Space>("spaces");
+        spaces
+            .update_one(
+                doc! {
+                    "id": &self.id,
+                },
+                doc! {
+                    "$push": {
+                        "channels": id,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+    // Channels are shown in the order they are listed in. The update only
+    // applies if nobody changed the list since it was read, and the new order
+    // and every category move are applied in one transaction, so that a
+    // failure cannot leave the space half reordered
+    pub async fn reorder_channels(
+        &self,
+        channel_ids: Vec<String>,
+        moves: &[CategoryMove],
+    ) -> Result<Space> {
+        let database = super::get_database();
+        let mut session = super::get_connection().start_session().await?;
+        session.start_transaction().await?;
+        let space = database
+            .collection::<Space>("spaces")
+            .find_one_and_update(
+                doc! {
+                    "id": &self.id,
+                    "channels": &self.channels,
+                },
+                doc! {
+                    "$set": {
+                        "channels": channel_ids,
+                    },
+                },
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .session(&mut session)
+            .await?;
+        let Some(space) = space else {
+            session.abort_transaction().await?;
+            return Err(Error::InvalidChannelOrder);
+        };
+        let channels = database.collection::<Channel>("channels");
+        for category_move in moves {
+            let mut update = doc! {
+                "category_id": &category_move.category_id,
+                "permissions_synced": category_move.synced,
+            };
+            if let Some(permissions) = &category_move.permissions {
+                update.insert("permissions", bson::to_bson(permissions)?);
+            }
+            let result = channels
+                .update_one(
+                    doc! {
+                        "id": &category_move.channel_id,
+                        "space_id": &self.id,
+                        "type": { "$in": ["INFORMATION_CHANNEL", "ANNOUNCEMENT_CHANNEL", "CHAT_CHANNEL"] },
+                    },
+                    doc! {
+                        "$set": update,
+                    },
+                )
+                .session(&mut session)
+                .await?;
+            if result.matched_count == 0 {
+                session.abort_transaction().await?;
+                return Err(Error::InvalidChannelOrder);
+            }
+        }
+        session.commit_transaction().await?;
+        Ok(space)
+    }
+
+    pub async fn remove_channel(&self, id: &String) -> Result<()> {
+        let spaces = super::get_database().collection::<Space>("spaces");
+        spaces
+            .update_one(
+                doc! {
+                    "id": &self.id,
+                },
+                doc! {
+                    "$pull": {
+                        "channels": id,
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
     pub async fn remove_member(&self, id: &String) -> Result<()> {
         let spaces = super::get_database().collection::<Space>("spaces");
         spaces
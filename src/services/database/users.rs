@@ -28,6 +28,16 @@ pub enum Relationship {
     Pending = 3,
 }
 
+// Who may open a direct message with the user; friends are always allowed
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectMessagePrivacy {
+    #[default]
+    Everyone = 0,
+    Friends = 1,
+    SharedSpaces = 2,
+}
+
 // TODO: allow disabling of friend requests
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Affinity {
@@ -41,6 +51,11 @@ pub struct User {
     pub profile_banner: Option<String>, // TODO: Make use of file handling
     pub profile_description: String,
     pub affinities: Vec<Affinity>,
+    #[serde(default)]
+    pub direct_message_privacy: DirectMessagePrivacy,
+    // Bot accounts are flagged by operators and may send rich embeds
+    #[serde(default)]
+    pub bot: bool,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub online: Option<bool>,
@@ -101,6 +116,11 @@ impl User {
             Channel::InformationChannel { space_id, .. } => self.in_space(space_id).await,
             Channel::AnnouncementChannel { space_id, .. } => self.in_space(space_id).await,
             Channel::ChatChannel { space_id, .. } => self.in_space(space_id).await,
+            Channel::CategoryChannel { space_id, .. } => self.in_space(space_id).await,
+            Channel::ThreadChannel { parent_id, .. } => {
+                let parent = Channel::get(parent_id).await?;
+                Box::pin(self.in_channel(&parent)).await
+            }
         }
     }
 
@@ -124,6 +144,8 @@ impl User {
             profile_banner: None,
             profile_description: String::new(),
             affinities: Vec::new(),
+            direct_message_privacy: DirectMessagePrivacy::Everyone,
+            bot: false,
             online: None,
             presence: None,
         };
@@ -131,6 +153,51 @@ impl User {
         Ok(user)
     }
 
+    pub fn is_friends_with(&self, other_id: &String) -> bool {
+        self.affinities
+            .iter()
+            .any(|a| &a.id == other_id && a.relationship == Relationship::Friend)
+    }
+
+    pub async fn shares_space_with(&self, other_id: &String) -> Result<bool> {
+        let spaces = super::get_database().collection::<Space>("spaces");
+        let space = spaces
+            .find_one(doc! {
+                "members": {
+                    "$all": [&self.id, other_id],
+                },
+            })
+            .await?;
+        Ok(space.is_some())
+    }
+
+    pub async fn set_direct_message_privacy(&self, privacy: &DirectMessagePrivacy) -> Result<()> {
+        let users = super::get_database().collection::<User>("users");
+        users
+            .update_one(
+                doc! {
+                    "id": &self.id
+                },
+                doc! {
+                    "$set": {
+                        "direct_message_privacy": bson::to_bson(privacy)?
+                    }
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Either side having blocked the other counts
+    pub fn is_blocked_with(&self, other: &User) -> bool {
+        let blocked = |user: &User, id: &String| {
+            user.affinities
+                .iter()
+                .any(|a| &a.id == id && a.relationship == Relationship::Blocked)
+        };
+        blocked(self, &other.id) || blocked(other, &self.id)
+    }
+
     pub async fn add_friend(&self, friend_id: &String) -> Result<()> {
         let users = super::get_database().collection::<User>("users");
         User::get(friend_id).await?;
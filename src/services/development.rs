@@ -0,0 +1,145 @@
+use jsonwebtoken::{encode, EncodingKey, Header};
+use log::info;
+use ulid::Ulid;
+
+use crate::{
+    errors::{Error, Result},
+    methods::authentication::UserJwt,
+    services::permissions::{Permission, PermissionSet},
+};
+
+use super::{
+    database::{
+        channels::Channel,
+        members::Member,
+        roles::{Color, Role},
+        spaces::Space,
+        users::User,
+    },
+    environment::{DEV_MODE, JWT_SECRET},
+};
+
+pub const DEMO_USER_ID: &str = "demo";
+
+// Tokens are normally issued by the SSO system; this stands in for it
+// during local development only
+pub fn mint_token(user_id: &str, lifetime: i64) -> Result<String> {
+    if !*DEV_MODE {
+        return Err(Error::Unimplemented);
+    }
+    let time = chrono::Utc::now().timestamp_millis();
+    let claims = UserJwt {
+        id: user_id.to_owned(),
+        issued_at: time as u128,
+        expires_at: (time + lifetime) as u128,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )?;
+    Ok(token)
+}
+
+pub async fn seed() -> Result<()> {
+    if !*DEV_MODE {
+        return Err(Error::Unimplemented);
+    }
+    let user = match User::get(&DEMO_USER_ID.to_owned()).await {
+        Ok(user) => user,
+        Err(Error::NotFound) => User::create(DEMO_USER_ID.to_owned()).await?,
+        Err(e) => return Err(e),
+    };
+    if !user.get_spaces().await?.is_empty() {
+        info!("Demo data already present, skipping seed");
+        return Ok(());
+    }
+
+    let space = Space::create(
+        "Demo Space".to_owned(),
+        Some("A space for local development".to_owned()),
+        user.id.clone(),
+        None,
+    )
+    .await?;
+
+    let mut moderator_permissions = PermissionSet::new();
+    for permission in [
+        Permission::ManageMessages,
+        Permission::ManageChannels,
+        Permission::ManageInvites,
+        Permission::MentionAll,
+    ] {
+        moderator_permissions.add_permission(permission);
+    }
+    let moderator = Role::create(
+        &space,
+        "Moderator".to_owned(),
+        moderator_permissions.to_i64(),
+        Color {
+            red: 46,
+            green: 204,
+            blue: 113,
+        },
+    )
+    .await?;
+    let member = Role::create(
+        &space,
+        "Member".to_owned(),
+        space.base_permissions,
+        Color {
+            red: 149,
+            green: 165,
+            blue: 166,
+        },
+    )
+    .await?;
+    Member::create(
+        &user.id,
+        &space.id,
+        vec![moderator.id.clone(), member.id.clone()],
+    )
+    .await?;
+
+    let channels = vec![
+        Channel::InformationChannel {
+            id: Ulid::new().to_string(),
+            name: "welcome".to_owned(),
+            description: String::new(),
+            space_id: space.id.clone(),
+            scope_id: space.scope_id.clone(),
+            permissions: Vec::new(),
+            category_id: None,
+            permissions_synced: false,
+        },
+        Channel::AnnouncementChannel {
+            id: Ulid::new().to_string(),
+            name: "announcements".to_owned(),
+            description: String::new(),
+            space_id: space.id.clone(),
+            scope_id: space.scope_id.clone(),
+            permissions: Vec::new(),
+            announcer_role_id: Some(moderator.id.clone()),
+            category_id: None,
+            permissions_synced: false,
+        },
+        Channel::ChatChannel {
+            id: Ulid::new().to_string(),
+            name: "general".to_owned(),
+            description: "General discussion".to_owned(),
+            space_id: space.id.clone(),
+            scope_id: space.scope_id.clone(),
+            permissions: Vec::new(),
+            category_id: None,
+            permissions_synced: false,
+            slowmode_seconds: 0,
+        },
+    ];
+    for channel in channels {
+        channel.create().await?;
+        space.add_channel(channel.id()).await?;
+    }
+
+    info!("Seeded demo space {}", space.id);
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::{sync::Arc, time::Duration};
+
+use async_std::{future, task::spawn};
+use async_tungstenite::tungstenite::Message;
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use log::{error, warn};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    methods::{Event, RpcApiEvent},
+};
+
+use super::{
+    redis::{get_connection, get_pubsub},
+    socket::{deserialize, serialize, RpcClient},
+};
+
+// Messages exchanged between instances over redis
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Dispatch {
+    Event {
+        user_ids: Vec<String>,
+        event: Box<Event>,
+    },
+    RevokeSession {
+        user_id: String,
+        session_id: String,
+    },
+}
+
+pub async fn publish(dispatch: Dispatch) -> Result<()> {
+    let mut redis = get_connection().await;
+    let data = serialize(&dispatch).map_err(|_| Error::InternalError)?;
+    redis.publish::<_, _, ()>("dispatch", data).await?;
+    Ok(())
+}
+
+pub async fn dispatch_event(user_ids: Vec<String>, event: Event) -> Result<()> {
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+    publish(Dispatch::Event {
+        user_ids,
+        event: Box::new(event),
+    })
+    .await
+}
+
+pub async fn send_event(client: &RpcClient, event: Event) {
+    let value = RpcApiEvent { event };
+    let buffer = serialize(&value).expect("Failed to serialize");
+    let socket = client.socket.clone();
+    future::timeout(Duration::from_millis(5000), async move {
+        socket.send(Message::Binary(buffer)).await
+    })
+    .await
+    .unwrap_or(Ok(()))
+    .unwrap_or_else(|e| warn!("Failed to send event: {e:?}"));
+}
+
+fn local_clients(
+    clients: &Arc<DashMap<String, RpcClient>>,
+    filter: impl Fn(&RpcClient) -> bool,
+) -> Vec<RpcClient> {
+    clients
+        .iter()
+        .filter(|c| filter(c.value()))
+        .map(|c| c.value().clone())
+        .collect()
+}
+
+pub fn spawn_listener(clients: Arc<DashMap<String, RpcClient>>) {
+    spawn(async move {
+        loop {
+            let mut pubsub = get_pubsub().await;
+            if let Err(e) = pubsub.subscribe("dispatch").await {
+                error!("Failed to subscribe to dispatch: {e}");
+                continue;
+            }
+            let mut messages = pubsub.on_message();
+            while let Some(msg) = messages.next().await {
+                let payload: Vec<u8> = match msg.get_payload() {
+                    Ok(payload) => payload,
+                    Err(_) => continue,
+                };
+                let dispatch: Dispatch = match deserialize(&payload) {
+                    Ok(dispatch) => dispatch,
+                    Err(_) => continue,
+                };
+                match dispatch {
+                    Dispatch::Event { user_ids, event } => {
+                        let targets = local_clients(&clients, |c| {
+                            c.user.as_ref().is_some_and(|u| user_ids.contains(&u.id))
+                        });
+                        for client in targets {
+                            send_event(&client, *event.clone()).await;
+                        }
+                    }
+                    Dispatch::RevokeSession {
+                        user_id,
+                        session_id,
+                    } => {
+                        let owned = clients
+                            .get(&session_id)
+                            .is_some_and(|c| c.user.as_ref().is_some_and(|u| u.id == user_id));
+                        if owned {
+                            if let Some((_, client)) = clients.remove(&session_id) {
+                                client.socket.close();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
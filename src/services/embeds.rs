@@ -0,0 +1,549 @@
+use std::{
+    io::{self, Read},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use async_std::{
+    channel::{bounded, Sender},
+    task::{spawn, spawn_blocking},
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use redis::AsyncCommands;
+use url::Url;
+
+use crate::{
+    errors::Result,
+    methods::{Event, MessageUpdatedEvent},
+};
+
+use super::{
+    database::{
+        channels::Channel,
+        embeds::{Embed, EmbedKind},
+        messages::Message,
+        roles::Color,
+    },
+    dispatch::dispatch_event,
+    markdown,
+    redis::get_connection,
+    socket::{deserialize, serialize},
+};
+
+const MAX_LINKS: usize = 5;
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+const CACHE_TTL: u64 = 24 * 60 * 60;
+const WORKER_COUNT: usize = 4;
+// Unfurling is best effort, so messages are skipped while the queue is full
+const QUEUE_SIZE: usize = 256;
+
+static QUEUE: OnceCell<Sender<Message>> = OnceCell::new();
+
+// Links must never be used to reach the server's own network
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b == 18 || b == 19)))
+        }
+        IpAddr::V6(ip) => {
+            // IPv4-mapped and IPv4-compatible addresses, including :: and ::1
+            if let Some(ip) = ip.to_ipv4() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            let [.., a, b, c, d] = ip.octets();
+            // NAT64 addresses embed an IPv4 address
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+            // So do 6to4 addresses, right after the prefix
+            if segments[0] == 0x2002 {
+                let [_, _, a, b, c, d, ..] = ip.octets();
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+            // Teredo relays to an IPv4 address the client controls, and the
+            // local-use NAT64 prefix maps into the local network
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || segments[..2] == [0x2001, 0]
+                || segments[..2] == [0x2001, 0xdb8]
+                || segments[..3] == [0x64, 0xff9b, 1]
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// Used for every connection, including redirects
+struct PublicResolver;
+
+impl ureq::Resolver for PublicResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let addresses: Vec<SocketAddr> = netloc
+            .to_socket_addrs()?
+            .filter(|a| is_public(a.ip()))
+            .collect();
+        if addresses.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{netloc} does not resolve to a public address"),
+            ));
+        }
+        Ok(addresses)
+    }
+}
+
+lazy_static! {
+    static ref AGENT: ureq::Agent = ureq::AgentBuilder::new()
+        .resolver(PublicResolver)
+        .timeout(Duration::from_secs(5))
+        .redirects(3)
+        .user_agent("Mozilla/5.0 (compatible; Harmony/1.0; +https://nextflow.cloud)")
+        .build();
+}
+
+pub fn spawn_worker() {
+    let (sender, receiver) = bounded::<Message>(QUEUE_SIZE);
+    if QUEUE.set(sender).is_err() {
+        panic!("Failed to set embed queue");
+    }
+    for _ in 0..WORKER_COUNT {
+        let receiver = receiver.clone();
+        spawn(async move {
+            while let Ok(message) = receiver.recv().await {
+                if let Err(e) = unfurl_message(message).await {
+                    error!("Failed to unfurl links: {e}");
+                }
+            }
+        });
+    }
+    info!("Started {WORKER_COUNT} embed workers");
+}
+
+pub fn queue(message: &Message) {
+    if extract_links(message).is_empty() {
+        return;
+    }
+    if let Some(queue) = QUEUE.get() {
+        let _ = queue.try_send(message.clone());
+    }
+}
+
+fn extract_links(message: &Message) -> Vec<Url> {
+    let mut links = markdown::embeddable_links(&message.ast);
+    links.truncate(MAX_LINKS);
+    links
+}
+
+async fn unfurl_message(message: Message) -> Result<()> {
+    let mut embeds = Vec::new();
+    for link in extract_links(&message) {
+        if let Some(embed) = get_embed(link).await? {
+            embeds.push(embed);
+        }
+    }
+    if embeds.is_empty() {
+        return Ok(());
+    }
+    let Some(message) = message.set_link_embeds(embeds).await? else {
+        return Ok(());
+    };
+    let channel = Channel::get(&message.channel_id).await?;
+    dispatch_event(
+        channel.get_viewers().await?,
+        Event::MessageUpdated(MessageUpdatedEvent {
+            channel_id: message.channel_id.clone(),
+            message,
+        }),
+    )
+    .await
+}
+
+async fn get_embed(url: Url) -> Result<Option<Embed>> {
+    let mut redis = get_connection().await;
+    let key = format!("embed:{url}");
+    let cached: Option<Vec<u8>> = redis.get(&key).await?;
+    if let Some(embed) = cached.and_then(|c| deserialize::<Option<Embed>>(&c).ok()) {
+        return Ok(embed);
+    }
+    let embed = spawn_blocking(move || fetch_embed(&url)).await;
+    // Failures are cached too, so a broken link is not fetched repeatedly
+    if let Ok(data) = serialize(&embed) {
+        redis.set_ex::<_, _, ()>(&key, data, CACHE_TTL).await?;
+    }
+    Ok(embed)
+}
+
+fn read_body(response: ureq::Response) -> Option<String> {
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_end(&mut body)
+        .ok()?;
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn fetch_embed(url: &Url) -> Option<Embed> {
+    let response = AGENT.get(url.as_str()).call().ok()?;
+    let final_url = Url::parse(response.get_url()).ok()?;
+    let mut embed = Embed {
+        kind: EmbedKind::Link,
+        url: Some(url.to_string()),
+        title: None,
+        description: None,
+        site_name: None,
+        image_url: None,
+        color: None,
+    };
+    let content_type = response.content_type().to_owned();
+    if content_type.starts_with("image/") {
+        embed.image_url = Some(final_url.to_string());
+        return Some(embed);
+    }
+    if content_type != "text/html" && content_type != "application/xhtml+xml" {
+        return None;
+    }
+    let html = read_body(response)?;
+    let page = parse_head(&html);
+    let meta = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            page.meta
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        })
+    };
+    embed.title = meta(&["og:title", "twitter:title"]).or(page.title);
+    embed.description = meta(&["og:description", "twitter:description", "description"]);
+    embed.site_name = meta(&["og:site_name"]);
+    embed.image_url = meta(&["og:image", "og:image:url", "twitter:image"])
+        .and_then(|image| final_url.join(&image).ok())
+        .filter(|image| image.scheme() == "http" || image.scheme() == "https")
+        .map(|image| image.to_string());
+    embed.color = meta(&["theme-color"]).and_then(|color| parse_color(&color));
+    if let Some(oembed) = page
+        .oembed_url
+        .and_then(|href| final_url.join(&href).ok())
+        .filter(|href| href.scheme() == "http" || href.scheme() == "https")
+    {
+        apply_oembed(&mut embed, &oembed);
+    }
+    embed.title = embed.title.map(|t| truncate(&t, 256));
+    embed.description = embed.description.map(|d| truncate(&d, 350));
+    embed.site_name = embed.site_name.map(|s| truncate(&s, 256));
+    if embed.title.is_none() && embed.description.is_none() && embed.image_url.is_none() {
+        return None;
+    }
+    Some(embed)
+}
+
+fn apply_oembed(embed: &mut Embed, url: &Url) {
+    let Some(body) = AGENT.get(url.as_str()).call().ok().and_then(read_body) else {
+        return;
+    };
+    let Ok(oembed) = serde_json::from_str::<serde_json::Value>(&body) else {
+        return;
+    };
+    let field = |name: &str| oembed.get(name).and_then(|v| v.as_str()).map(str::to_owned);
+    if embed.title.is_none() {
+        embed.title = field("title");
+    }
+    if let Some(provider) = field("provider_name") {
+        embed.site_name = Some(provider);
+    }
+    if embed.image_url.is_none() {
+        embed.image_url = field("thumbnail_url")
+            .filter(|u| Url::parse(u).is_ok_and(|u| u.scheme() == "http" || u.scheme() == "https"));
+    }
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    let text = text.trim();
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color {
+        red: ((value >> 16) & 0xff) as i32,
+        green: ((value >> 8) & 0xff) as i32,
+        blue: (value & 0xff) as i32,
+    })
+}
+
+#[derive(Default)]
+struct PageHead {
+    title: Option<String>,
+    meta: Vec<(String, String)>,
+    oembed_url: Option<String>,
+}
+
+// A deliberately small scanner for <title>, <meta> and <link> tags; the
+// lowercased copy keeps byte offsets identical to the original
+fn parse_head(html: &str) -> PageHead {
+    let lower = html.to_ascii_lowercase();
+    let mut head = PageHead::default();
+    if let Some(start) = lower.find("<title") {
+        if let Some(open_end) = lower[start..].find('>') {
+            let content_start = start + open_end + 1;
+            if let Some(end) = lower[content_start..].find("</title") {
+                let title = decode_entities(html[content_start..content_start + end].trim());
+                if !title.is_empty() {
+                    head.title = Some(title);
+                }
+            }
+        }
+    }
+    let mut position = 0;
+    while let Some(offset) = lower[position..].find('<') {
+        let start = position + offset + 1;
+        let Some(length) = lower[start..].find('>') else {
+            break;
+        };
+        let tag = &html[start..start + length];
+        position = start + length;
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let tag_name = tag[..name_end].to_ascii_lowercase();
+        match tag_name.as_str() {
+            "/head" | "body" => break,
+            "meta" | "link" => {}
+            _ => continue,
+        }
+        let attributes = parse_attributes(&tag[name_end..]);
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        if tag_name == "meta" {
+            let name = attribute("property").or_else(|| attribute("name"));
+            if let (Some(name), Some(content)) = (name, attribute("content")) {
+                head.meta.push((name.to_ascii_lowercase(), content));
+            }
+        } else if attribute("type").is_some_and(|t| t == "application/json+oembed") {
+            head.oembed_url = attribute("href");
+        }
+    }
+    head
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (value, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], &inner[(end + 1).min(inner.len())..])
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            rest = remaining.trim_start();
+            decode_entities(value)
+        } else {
+            rest = rest.trim_start_matches('/').trim_start();
+            String::new()
+        };
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let entity = after
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| (&after[..end], end));
+        let character = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => name
+                .strip_prefix("#x")
+                .or_else(|| name.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| name.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                decoded.push(character);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = after;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        assert!(public("93.184.216.34"));
+        assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+        assert!(public("::ffff:93.184.216.34"));
+        assert!(public("2002:5db8:d822::1"));
+        assert!(public("64:ff9b::5db8:d822"));
+    }
+
+    #[test]
+    fn rejects_internal_ipv4() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.8",
+            "198.18.0.1",
+            "0.0.0.0",
+            "240.0.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn rejects_internal_ipv6() {
+        for ip in [
+            "::",
+            "::1",
+            "fc00::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+            "64:ff9b:1::1",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn unwraps_embedded_ipv4() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:7f00:1::1",
+            "2002:c0a8:101::1",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(
+            decode_entities("&lt;b&gt; &quot;x&quot; &apos;y&apos;"),
+            "<b> \"x\" 'y'"
+        );
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;"), "ABC");
+        assert_eq!(decode_entities("a&nbsp;b"), "a b");
+    }
+
+    #[test]
+    fn keeps_unknown_entities() {
+        assert_eq!(decode_entities("AT&T"), "AT&T");
+        assert_eq!(decode_entities("&unknown; &"), "&unknown; &");
+        assert_eq!(decode_entities("&#xD800;"), "&#xD800;");
+        assert_eq!(
+            decode_entities("&averyveryverylongname;"),
+            "&averyveryverylongname;"
+        );
+    }
+
+    #[test]
+    fn parses_head() {
+        let head = parse_head(
+            r#"<html><HEAD><Title> Example &amp; Co </Title>
+            <meta property="og:title" content="OG title">
+            <META NAME='Description' CONTENT='Some &quot;text&quot;'/>
+            <meta name=theme-color content=#ff0000>
+            <link rel="alternate" type="application/json+oembed" href="/oembed?url=x">
+            </head><body><meta property="og:image" content="ignored"></body></html>"#,
+        );
+        assert_eq!(head.title.as_deref(), Some("Example & Co"));
+        assert_eq!(
+            head.meta,
+            vec![
+                ("og:title".to_owned(), "OG title".to_owned()),
+                ("description".to_owned(), "Some \"text\"".to_owned()),
+                ("theme-color".to_owned(), "#ff0000".to_owned()),
+            ]
+        );
+        assert_eq!(head.oembed_url.as_deref(), Some("/oembed?url=x"));
+    }
+
+    #[test]
+    fn parses_broken_head() {
+        let head = parse_head("<title>Unclosed <meta content=\"x\" name=\"a\"");
+        assert!(head.title.is_none());
+        assert!(head.meta.is_empty());
+        let head = parse_head("<meta name=\"a\" content=\"unterminated>");
+        assert_eq!(head.meta, vec![("a".to_owned(), "unterminated".to_owned())]);
+    }
+}
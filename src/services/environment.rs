@@ -11,7 +11,46 @@ lazy_static! {
         .unwrap_or_else(|_| "200".to_string())
         .parse::<i16>()
         .expect("MAX_SPACE_COUNT must be an integer");
+    pub static ref THREAD_ARCHIVE_AFTER: i64 = env::var("THREAD_ARCHIVE_AFTER")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse::<i64>()
+        .expect("THREAD_ARCHIVE_AFTER must be an integer");
+    pub static ref MAX_EMOJI_COUNT: u64 = env::var("MAX_EMOJI_COUNT")
+        .unwrap_or_else(|_| "50".to_string())
+        .parse::<u64>()
+        .expect("MAX_EMOJI_COUNT must be an integer");
+    pub static ref MAX_GROUP_MEMBERS: u64 = env::var("MAX_GROUP_MEMBERS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .expect("MAX_GROUP_MEMBERS must be an integer");
+    pub static ref MAX_PIN_COUNT: u64 = env::var("MAX_PIN_COUNT")
+        .unwrap_or_else(|_| "50".to_string())
+        .parse::<u64>()
+        .expect("MAX_PIN_COUNT must be an integer");
+    pub static ref MAX_ATTACHMENT_SIZE: i64 = env::var("MAX_ATTACHMENT_SIZE")
+        .unwrap_or_else(|_| "26214400".to_string())
+        .parse::<i64>()
+        .expect("MAX_ATTACHMENT_SIZE must be an integer");
+    pub static ref UPLOAD_EXPIRE_AFTER: i64 = env::var("UPLOAD_EXPIRE_AFTER")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse::<i64>()
+        .expect("UPLOAD_EXPIRE_AFTER must be an integer");
+    pub static ref STORAGE_BACKEND: String =
+        env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_string());
+    pub static ref STORAGE_PATH: String =
+        env::var("STORAGE_PATH").unwrap_or_else(|_| "data".to_string());
+    pub static ref S3_ENDPOINT: String = env::var("S3_ENDPOINT").expect("S3_ENDPOINT must be set");
+    pub static ref S3_BUCKET: String = env::var("S3_BUCKET").expect("S3_BUCKET must be set");
+    pub static ref S3_REGION: String =
+        env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    pub static ref S3_ACCESS_KEY: String =
+        env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set");
+    pub static ref S3_SECRET_KEY: String =
+        env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set");
     pub static ref LISTEN_ADDRESS: String =
         env::var("LISTEN_ADDRESS").unwrap_or_else(|_| "0.0.0.0:9000".to_string());
+    pub static ref DEV_MODE: bool = env::var("DEV_MODE")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
     pub static ref REDIS_URI: String = env::var("REDIS_URI").expect("REDIS_URI must be set");
 }
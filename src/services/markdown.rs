@@ -0,0 +1,740 @@
+use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use url::Url;
+
+use crate::{
+    errors::{Error, Result},
+    globals::{MAX_FORMATTING_DEPTH, MAX_MESSAGE_BYTES, MAX_MESSAGE_LENGTH},
+    services::database::{emojis::validate_name, messages::Mentions},
+};
+
+// Normalized form of a message's markdown, so that every client renders
+// the same thing. Raw HTML is never interpreted and is kept as text.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ContentNode {
+    Text {
+        text: String,
+    },
+    Paragraph {
+        children: Vec<ContentNode>,
+    },
+    Heading {
+        level: u8,
+        children: Vec<ContentNode>,
+    },
+    BlockQuote {
+        children: Vec<ContentNode>,
+    },
+    #[serde(rename_all = "camelCase")]
+    CodeBlock {
+        language: Option<String>,
+        text: String,
+    },
+    List {
+        start: Option<u64>,
+        children: Vec<ContentNode>,
+    },
+    Item {
+        children: Vec<ContentNode>,
+    },
+    Emphasis {
+        children: Vec<ContentNode>,
+    },
+    Strong {
+        children: Vec<ContentNode>,
+    },
+    Strikethrough {
+        children: Vec<ContentNode>,
+    },
+    Code {
+        text: String,
+    },
+    // Links wrapped in <...> are not unfurled
+    Link {
+        url: String,
+        embed: bool,
+        children: Vec<ContentNode>,
+    },
+    LineBreak,
+    Rule,
+    #[serde(rename_all = "camelCase")]
+    UserMention {
+        user_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    RoleMention {
+        role_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ChannelMention {
+        channel_id: String,
+    },
+    Everyone,
+    Here,
+    Emoji {
+        name: String,
+    },
+}
+
+// Everything a message references, collected while building the tree
+#[derive(Clone, Debug, Default)]
+pub struct ParsedContent {
+    pub nodes: Vec<ContentNode>,
+    pub mentions: Mentions,
+    pub emojis: Vec<String>,
+}
+
+enum Frame {
+    Root,
+    Paragraph,
+    Heading(u8),
+    BlockQuote,
+    CodeBlock(Option<String>, String),
+    List(Option<u64>),
+    Item,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link(String, bool),
+    // Markup that is not supported; its contents are kept in the parent
+    Transparent,
+}
+
+struct Builder {
+    stack: Vec<(Frame, Vec<ContentNode>)>,
+    text: String,
+    parsed: ParsedContent,
+}
+
+impl Builder {
+    fn children(&mut self) -> &mut Vec<ContentNode> {
+        &mut self.stack.last_mut().expect("root frame").1
+    }
+
+    // Adjacent text is merged so that the tree stays normalized
+    fn append(&mut self, node: ContentNode) {
+        let children = self.children();
+        match (children.last_mut(), node) {
+            (Some(ContentNode::Text { text }), ContentNode::Text { text: next }) => {
+                text.push_str(&next)
+            }
+            (_, node) => children.push(node),
+        }
+    }
+
+    fn in_link(&self) -> bool {
+        self.stack
+            .iter()
+            .any(|(frame, _)| matches!(frame, Frame::Link(..)))
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<()> {
+        self.flush();
+        if self.stack.len() > *MAX_FORMATTING_DEPTH {
+            return Err(Error::InvalidFormatting);
+        }
+        self.stack.push((frame, Vec::new()));
+        Ok(())
+    }
+
+    fn pop(&mut self) {
+        self.flush();
+        let Some((frame, children)) = self.stack.pop() else {
+            return;
+        };
+        let node = match frame {
+            Frame::Root => return,
+            Frame::Paragraph => ContentNode::Paragraph { children },
+            Frame::Heading(level) => ContentNode::Heading { level, children },
+            Frame::BlockQuote => ContentNode::BlockQuote { children },
+            Frame::CodeBlock(language, text) => ContentNode::CodeBlock { language, text },
+            Frame::List(start) => ContentNode::List { start, children },
+            Frame::Item => ContentNode::Item { children },
+            Frame::Emphasis => ContentNode::Emphasis { children },
+            Frame::Strong => ContentNode::Strong { children },
+            Frame::Strikethrough => ContentNode::Strikethrough { children },
+            Frame::Link(url, embed) => ContentNode::Link {
+                url,
+                embed,
+                children,
+            },
+            Frame::Transparent => {
+                for child in children {
+                    self.append(child);
+                }
+                return;
+            }
+        };
+        self.append(node);
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((Frame::CodeBlock(_, code), _)) = self.stack.last_mut() {
+            code.push_str(text);
+        } else {
+            // Text arrives in pieces; mentions and emoji can span them
+            self.text.push_str(text);
+        }
+    }
+
+    fn node(&mut self, node: ContentNode) {
+        self.flush();
+        self.append(node);
+    }
+
+    fn flush(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.text);
+        let in_link = self.in_link();
+        let mut plain = String::new();
+        let mut prev = None;
+        let mut rest = text.as_str();
+        while let Some(c) = rest.chars().next() {
+            if let Some((node, length)) = match_token(rest, prev, in_link) {
+                if !plain.is_empty() {
+                    let text = std::mem::take(&mut plain);
+                    self.append(ContentNode::Text { text });
+                }
+                self.record(&node);
+                self.append(node);
+                prev = rest[..length].chars().last();
+                rest = &rest[length..];
+                continue;
+            }
+            plain.push(c);
+            prev = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        if !plain.is_empty() {
+            self.append(ContentNode::Text { text: plain });
+        }
+    }
+
+    fn record(&mut self, node: &ContentNode) {
+        let mentions = &mut self.parsed.mentions;
+        let (list, id) = match node {
+            ContentNode::UserMention { user_id } => (&mut mentions.users, user_id),
+            ContentNode::RoleMention { role_id } => (&mut mentions.roles, role_id),
+            ContentNode::ChannelMention { channel_id } => (&mut mentions.channels, channel_id),
+            ContentNode::Emoji { name } => (&mut self.parsed.emojis, name),
+            ContentNode::Everyone => {
+                mentions.everyone = true;
+                return;
+            }
+            ContentNode::Here => {
+                mentions.here = true;
+                return;
+            }
+            _ => return,
+        };
+        if !list.contains(id) {
+            list.push(id.clone());
+        }
+    }
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Recognizes <@user>, <@&role>, <#channel>, @everyone, @here, :emoji: and
+// bare links at the start of the text, returning the node and its length
+fn match_token(text: &str, prev: Option<char>, in_link: bool) -> Option<(ContentNode, usize)> {
+    let at_boundary = prev.is_none_or(|c| !(c.is_alphanumeric() || c == '@'));
+    if let Some(after) = text.strip_prefix('<') {
+        let end = after.find('>')?;
+        let inner = &after[..end];
+        let node = if let Some(id) = inner.strip_prefix("@&") {
+            valid_id(id).then(|| ContentNode::RoleMention {
+                role_id: id.to_owned(),
+            })
+        } else if let Some(id) = inner.strip_prefix('@') {
+            valid_id(id).then(|| ContentNode::UserMention {
+                user_id: id.to_owned(),
+            })
+        } else if let Some(id) = inner.strip_prefix('#') {
+            valid_id(id).then(|| ContentNode::ChannelMention {
+                channel_id: id.to_owned(),
+            })
+        } else {
+            None
+        };
+        return node.map(|node| (node, end + 2));
+    }
+    if text.starts_with('@') && at_boundary {
+        for (word, node) in [
+            ("@everyone", ContentNode::Everyone),
+            ("@here", ContentNode::Here),
+        ] {
+            let Some(after) = text.strip_prefix(word) else {
+                continue;
+            };
+            if !after.chars().next().is_some_and(|c| c.is_alphanumeric()) {
+                return Some((node, word.len()));
+            }
+        }
+        return None;
+    }
+    if let Some(after) = text.strip_prefix(':') {
+        let end = after.find(':')?;
+        let name = &after[..end];
+        return validate_name(name).is_ok().then(|| {
+            let node = ContentNode::Emoji {
+                name: name.to_owned(),
+            };
+            (node, end + 2)
+        });
+    }
+    if !in_link && at_boundary && (text.starts_with("http://") || text.starts_with("https://")) {
+        let word = text.split(char::is_whitespace).next().unwrap_or_default();
+        let word = word.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']);
+        let url = Url::parse(word).ok().filter(|url| url.host().is_some())?;
+        let node = ContentNode::Link {
+            url: url.to_string(),
+            embed: true,
+            children: vec![ContentNode::Text {
+                text: word.to_owned(),
+            }],
+        };
+        return Some((node, word.len()));
+    }
+    None
+}
+
+// Only web links are kept; anything else is rendered as its text
+fn parse_link(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+// Normalizes line endings and checks the length in grapheme clusters, so
+// that multibyte text is measured the way users see it
+pub fn validate(content: &str, allow_empty: bool) -> Result<String> {
+    let content = content.replace("\r\n", "\n");
+    let trimmed = content.trim();
+    if trimmed.len() > *MAX_MESSAGE_BYTES || trimmed.graphemes(true).count() > *MAX_MESSAGE_LENGTH {
+        return Err(Error::MessageTooLong);
+    }
+    if trimmed.is_empty() && !allow_empty {
+        return Err(Error::MessageEmpty);
+    }
+    if trimmed
+        .chars()
+        .any(|c| c.is_control() && c != '\n' && c != '\t')
+    {
+        return Err(Error::InvalidFormatting);
+    }
+    Ok(trimmed.to_owned())
+}
+
+// Builds the tree and extracts mentions and emoji in a single pass;
+// nothing inside code is treated as a reference
+pub fn parse(content: &str) -> Result<ParsedContent> {
+    let mut builder = Builder {
+        stack: vec![(Frame::Root, Vec::new())],
+        text: String::new(),
+        parsed: ParsedContent::default(),
+    };
+    let parser = Parser::new_ext(content, Options::ENABLE_STRIKETHROUGH);
+    for event in parser {
+        match event {
+            Event::Start(tag) => {
+                let frame = match tag {
+                    Tag::Paragraph => Frame::Paragraph,
+                    Tag::Heading { level, .. } => Frame::Heading(level as u8),
+                    Tag::BlockQuote(_) => Frame::BlockQuote,
+                    Tag::CodeBlock(kind) => {
+                        let language = match kind {
+                            CodeBlockKind::Fenced(info) => info
+                                .split_whitespace()
+                                .next()
+                                .map(|language| language.to_owned()),
+                            CodeBlockKind::Indented => None,
+                        };
+                        Frame::CodeBlock(language, String::new())
+                    }
+                    Tag::List(start) => Frame::List(start),
+                    Tag::Item => Frame::Item,
+                    Tag::Emphasis => Frame::Emphasis,
+                    Tag::Strong => Frame::Strong,
+                    Tag::Strikethrough => Frame::Strikethrough,
+                    Tag::Link {
+                        link_type,
+                        dest_url,
+                        ..
+                    }
+                    | Tag::Image {
+                        link_type,
+                        dest_url,
+                        ..
+                    } => match parse_link(&dest_url) {
+                        Some(url) if !builder.in_link() => {
+                            Frame::Link(url, link_type != LinkType::Autolink)
+                        }
+                        _ => Frame::Transparent,
+                    },
+                    _ => Frame::Transparent,
+                };
+                builder.push(frame)?;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                // The closing fence leaves a trailing newline behind
+                if let Some((Frame::CodeBlock(_, code), _)) = builder.stack.last_mut() {
+                    code.truncate(code.trim_end_matches('\n').len());
+                }
+                builder.pop();
+            }
+            Event::End(_) => builder.pop(),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => builder.text(&text),
+            Event::Code(text) => builder.node(ContentNode::Code {
+                text: text.into_string(),
+            }),
+            Event::SoftBreak | Event::HardBreak => builder.node(ContentNode::LineBreak),
+            Event::Rule => builder.node(ContentNode::Rule),
+            _ => {}
+        }
+    }
+    builder.flush();
+    builder.parsed.nodes = builder
+        .stack
+        .pop()
+        .map(|(_, nodes)| nodes)
+        .unwrap_or_default();
+    Ok(builder.parsed)
+}
+
+// References that did not resolve are turned back into the text they came from
+pub fn retain_references(
+    nodes: Vec<ContentNode>,
+    mentions: &Mentions,
+    emojis: &[String],
+) -> Vec<ContentNode> {
+    let mut retained: Vec<ContentNode> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let node = match node {
+            ContentNode::UserMention { user_id } if !mentions.users.contains(&user_id) => {
+                ContentNode::Text {
+                    text: format!("<@{user_id}>"),
+                }
+            }
+            ContentNode::RoleMention { role_id } if !mentions.roles.contains(&role_id) => {
+                ContentNode::Text {
+                    text: format!("<@&{role_id}>"),
+                }
+            }
+            ContentNode::ChannelMention { channel_id }
+                if !mentions.channels.contains(&channel_id) =>
+            {
+                ContentNode::Text {
+                    text: format!("<#{channel_id}>"),
+                }
+            }
+            ContentNode::Emoji { name } if !emojis.contains(&name) => ContentNode::Text {
+                text: format!(":{name}:"),
+            },
+            node => node.map_children(|children| retain_references(children, mentions, emojis)),
+        };
+        // Keep text merged so that the tree stays normalized
+        match (retained.last_mut(), node) {
+            (Some(ContentNode::Text { text }), ContentNode::Text { text: next }) => {
+                text.push_str(&next)
+            }
+            (_, node) => retained.push(node),
+        }
+    }
+    retained
+}
+
+// Links that may be unfurled into embeds, in the order they appear
+pub fn embeddable_links(nodes: &[ContentNode]) -> Vec<Url> {
+    let mut links = Vec::new();
+    for node in nodes {
+        match node {
+            ContentNode::Link {
+                url, embed: true, ..
+            } => {
+                if let Ok(url) = Url::parse(url) {
+                    if !links.contains(&url) {
+                        links.push(url);
+                    }
+                }
+            }
+            node => {
+                for link in embeddable_links(node.children()) {
+                    if !links.contains(&link) {
+                        links.push(link);
+                    }
+                }
+            }
+        }
+    }
+    links
+}
+
+impl ContentNode {
+    fn children(&self) -> &[ContentNode] {
+        match self {
+            ContentNode::Paragraph { children }
+            | ContentNode::Heading { children, .. }
+            | ContentNode::BlockQuote { children }
+            | ContentNode::List { children, .. }
+            | ContentNode::Item { children }
+            | ContentNode::Emphasis { children }
+            | ContentNode::Strong { children }
+            | ContentNode::Strikethrough { children }
+            | ContentNode::Link { children, .. } => children,
+            _ => &[],
+        }
+    }
+
+    fn map_children(self, f: impl FnOnce(Vec<ContentNode>) -> Vec<ContentNode>) -> ContentNode {
+        match self {
+            ContentNode::Paragraph { children } => ContentNode::Paragraph {
+                children: f(children),
+            },
+            ContentNode::Heading { level, children } => ContentNode::Heading {
+                level,
+                children: f(children),
+            },
+            ContentNode::BlockQuote { children } => ContentNode::BlockQuote {
+                children: f(children),
+            },
+            ContentNode::List { start, children } => ContentNode::List {
+                start,
+                children: f(children),
+            },
+            ContentNode::Item { children } => ContentNode::Item {
+                children: f(children),
+            },
+            ContentNode::Emphasis { children } => ContentNode::Emphasis {
+                children: f(children),
+            },
+            ContentNode::Strong { children } => ContentNode::Strong {
+                children: f(children),
+            },
+            ContentNode::Strikethrough { children } => ContentNode::Strikethrough {
+                children: f(children),
+            },
+            ContentNode::Link {
+                url,
+                embed,
+                children,
+            } => ContentNode::Link {
+                url,
+                embed,
+                children: f(children),
+            },
+            node => node,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> ContentNode {
+        ContentNode::Text {
+            text: text.to_owned(),
+        }
+    }
+
+    fn paragraph(children: Vec<ContentNode>) -> Vec<ContentNode> {
+        vec![ContentNode::Paragraph { children }]
+    }
+
+    #[test]
+    fn parses_mentions() {
+        let parsed = parse("hi <@alice> and <@&mods> in <#general> @everyone").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            paragraph(vec![
+                text("hi "),
+                ContentNode::UserMention {
+                    user_id: "alice".to_owned()
+                },
+                text(" and "),
+                ContentNode::RoleMention {
+                    role_id: "mods".to_owned()
+                },
+                text(" in "),
+                ContentNode::ChannelMention {
+                    channel_id: "general".to_owned()
+                },
+                text(" "),
+                ContentNode::Everyone,
+            ])
+        );
+        assert_eq!(
+            parsed.mentions,
+            Mentions {
+                users: vec!["alice".to_owned()],
+                roles: vec!["mods".to_owned()],
+                channels: vec!["general".to_owned()],
+                everyone: true,
+                here: false,
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_mass_mentions_inside_words() {
+        let parsed = parse("mail me@here or @hereafter").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            paragraph(vec![text("mail me@here or @hereafter")])
+        );
+        assert!(!parsed.mentions.is_mass_mention());
+    }
+
+    #[test]
+    fn parses_emoji_references() {
+        let parsed = parse(":wave: hello :x: :not valid:").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            paragraph(vec![
+                ContentNode::Emoji {
+                    name: "wave".to_owned()
+                },
+                text(" hello :x: :not valid:"),
+            ])
+        );
+        assert_eq!(parsed.emojis, vec!["wave".to_owned()]);
+    }
+
+    #[test]
+    fn parses_nested_formatting() {
+        let parsed = parse("**bold _and <@alice>_** ~~gone~~").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            paragraph(vec![
+                ContentNode::Strong {
+                    children: vec![
+                        text("bold "),
+                        ContentNode::Emphasis {
+                            children: vec![
+                                text("and "),
+                                ContentNode::UserMention {
+                                    user_id: "alice".to_owned()
+                                },
+                            ]
+                        },
+                    ]
+                },
+                text(" "),
+                ContentNode::Strikethrough {
+                    children: vec![text("gone")]
+                },
+            ])
+        );
+        assert_eq!(parsed.mentions.users, vec!["alice".to_owned()]);
+    }
+
+    #[test]
+    fn rejects_deeply_nested_formatting() {
+        let content = format!("{}deep", "> ".repeat(*MAX_FORMATTING_DEPTH + 1));
+        assert!(matches!(parse(&content), Err(Error::InvalidFormatting)));
+    }
+
+    #[test]
+    fn code_suppresses_references() {
+        let parsed = parse("```rust\n<@alice> :wave: @everyone\n```\n`<@bob>`").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            vec![
+                ContentNode::CodeBlock {
+                    language: Some("rust".to_owned()),
+                    text: "<@alice> :wave: @everyone".to_owned(),
+                },
+                ContentNode::Paragraph {
+                    children: vec![ContentNode::Code {
+                        text: "<@bob>".to_owned()
+                    }]
+                },
+            ]
+        );
+        assert_eq!(parsed.mentions, Mentions::default());
+        assert!(parsed.emojis.is_empty());
+    }
+
+    #[test]
+    fn parses_links() {
+        let parsed = parse("see https://example.com/a. or <https://example.com/b>").unwrap();
+        assert_eq!(
+            parsed.nodes,
+            paragraph(vec![
+                text("see "),
+                ContentNode::Link {
+                    url: "https://example.com/a".to_owned(),
+                    embed: true,
+                    children: vec![text("https://example.com/a")],
+                },
+                text(". or "),
+                ContentNode::Link {
+                    url: "https://example.com/b".to_owned(),
+                    embed: false,
+                    children: vec![text("https://example.com/b")],
+                },
+            ])
+        );
+        let links = embeddable_links(&parsed.nodes);
+        assert_eq!(links, vec![Url::parse("https://example.com/a").unwrap()]);
+    }
+
+    #[test]
+    fn retains_only_resolved_references() {
+        let parsed = parse("hi <@alice> <@bob> :wave: :gone:").unwrap();
+        let mentions = Mentions {
+            users: vec!["alice".to_owned()],
+            ..Default::default()
+        };
+        let nodes = retain_references(parsed.nodes, &mentions, &["wave".to_owned()]);
+        assert_eq!(
+            nodes,
+            paragraph(vec![
+                text("hi "),
+                ContentNode::UserMention {
+                    user_id: "alice".to_owned()
+                },
+                text(" <@bob> "),
+                ContentNode::Emoji {
+                    name: "wave".to_owned()
+                },
+                text(" :gone:"),
+            ])
+        );
+    }
+
+    #[test]
+    fn validates_content() {
+        assert_eq!(validate("  a\r\nb  ", false).unwrap(), "a\nb");
+        assert!(matches!(validate("   ", false), Err(Error::MessageEmpty)));
+        assert_eq!(validate("   ", true).unwrap(), "");
+        assert!(matches!(
+            validate("a\u{0}b", false),
+            Err(Error::InvalidFormatting)
+        ));
+    }
+
+    #[test]
+    fn counts_graphemes_against_the_length_limit() {
+        // Seven code points joined into a single grapheme cluster
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        assert_eq!(family.chars().count(), 7);
+        let longest = family.repeat(*MAX_MESSAGE_LENGTH);
+        assert!(longest.len() <= *MAX_MESSAGE_BYTES);
+        assert_eq!(validate(&longest, false).unwrap(), longest);
+        let too_long = family.repeat(*MAX_MESSAGE_LENGTH + 1);
+        assert!(matches!(
+            validate(&too_long, false),
+            Err(Error::MessageTooLong)
+        ));
+    }
+}
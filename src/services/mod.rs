@@ -1,8 +1,15 @@
 pub mod database;
+pub mod development;
+pub mod dispatch;
+pub mod embeds;
 pub mod encryption;
 pub mod environment;
+pub mod markdown;
 pub mod permissions;
 pub mod redis;
+pub mod sessions;
 pub mod socket;
+pub mod storage;
+pub mod tasks;
 pub mod webrtc;
 // pub mod logger;
@@ -30,6 +30,7 @@ pub enum Permission {
     Speak = 0x100000,                // 1 << 20
     Video = 0x200000,                // 1 << 21
     Screenshare = 0x400000,          // 1 << 22
+    ManageEmoji = 0x800000,          // 1 << 23
 }
 
 #[derive(Clone, Debug)]
@@ -63,6 +64,7 @@ impl Permission {
             Permission::Speak,
             Permission::Video,
             Permission::Screenshare,
+            Permission::ManageEmoji,
         ]
         .iter()
         .copied()
@@ -135,6 +137,16 @@ impl PermissionSet {
         permissions
     }
 
+    // Channel overrides may only touch permissions that apply to channels
+    pub fn is_channel_only(&self) -> bool {
+        let channel = Permission::iter_channel().fold(0, |mask, p| mask | p as i64);
+        self.permissions & !channel == 0
+    }
+
+    pub fn overlaps(&self, other: &PermissionSet) -> bool {
+        self.permissions & other.permissions != 0
+    }
+
     pub fn has_permission(&self, permission: Permission) -> bool {
         self.permissions & permission as i64 != 0
     }
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result},
+    globals::HEARTBEAT_TIMEOUT,
+};
+
+use super::{
+    redis::get_connection,
+    socket::{deserialize, serialize},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub device_name: Option<String>,
+    pub ip: String,
+    pub connected_at: i64,
+    pub last_heartbeat: i64,
+}
+
+impl Session {
+    // Sessions live in redis so that they can be listed and revoked
+    // from any instance
+    pub async fn save(&self) -> Result<()> {
+        let mut redis = get_connection().await;
+        let data = serialize(self).map_err(|_| Error::InternalError)?;
+        redis
+            .hset::<_, _, _, ()>(format!("sessions:{}", self.user_id), &self.id, data)
+            .await?;
+        Ok(())
+    }
+
+    // Heartbeats only update sessions that still exist, so that one that
+    // was in flight cannot bring back a revoked session
+    pub async fn refresh(&self) -> Result<bool> {
+        let mut redis = get_connection().await;
+        let data = serialize(self).map_err(|_| Error::InternalError)?;
+        let updated: bool = redis::Script::new(
+            r"
+            if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 1 then
+                redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+                return 1
+            end
+            return 0
+            ",
+        )
+        .key(format!("sessions:{}", self.user_id))
+        .arg(&self.id)
+        .arg(data)
+        .invoke_async(&mut redis)
+        .await?;
+        Ok(updated)
+    }
+
+    pub async fn get(user_id: &String, id: &String) -> Result<Option<Session>> {
+        let mut redis = get_connection().await;
+        let data: Option<Vec<u8>> = redis.hget(format!("sessions:{}", user_id), id).await?;
+        Ok(data.and_then(|d| deserialize(&d).ok()))
+    }
+
+    pub async fn get_all(user_id: &String) -> Result<Vec<Session>> {
+        let mut redis = get_connection().await;
+        let data: HashMap<String, Vec<u8>> = redis.hgetall(format!("sessions:{}", user_id)).await?;
+        let time = chrono::Utc::now().timestamp_millis();
+        let mut sessions = Vec::new();
+        for (id, bytes) in data {
+            let session: Option<Session> = deserialize(&bytes).ok();
+            match session {
+                Some(session) if session.last_heartbeat + *HEARTBEAT_TIMEOUT as i64 > time => {
+                    sessions.push(session);
+                }
+                // Left behind by an instance that went away without cleaning up
+                _ => {
+                    redis
+                        .hdel::<_, _, ()>(format!("sessions:{}", user_id), id)
+                        .await?;
+                }
+            }
+        }
+        sessions.sort_by_key(|s| s.connected_at);
+        Ok(sessions)
+    }
+
+    pub async fn is_online(user_id: &String) -> Result<bool> {
+        Ok(!Session::get_all(user_id).await?.is_empty())
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        let mut redis = get_connection().await;
+        redis
+            .hdel::<_, _, ()>(format!("sessions:{}", self.user_id), &self.id)
+            .await?;
+        Ok(())
+    }
+}
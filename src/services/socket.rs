@@ -9,6 +9,7 @@ use async_std::{
 use async_tungstenite::{accept_async, tungstenite::Message};
 use dashmap::DashMap;
 use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
 use rand::rngs::OsRng;
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
@@ -21,7 +22,7 @@ use crate::{
     services::encryption::generate_id,
 };
 
-use super::{database::users::User, environment::LISTEN_ADDRESS};
+use super::{database::users::User, dispatch, environment::LISTEN_ADDRESS, sessions::Session};
 
 #[derive(Clone)]
 pub struct RpcClient {
@@ -30,11 +31,15 @@ pub struct RpcClient {
     pub user: Option<Arc<User>>,
     pub request_ids: Vec<String>,
     pub heartbeat_tx: Arc<Sender<()>>,
+    pub ip: String,
+    pub connected_at: i64,
+    pub session: Option<Session>,
 }
 
 pub async fn start_server() {
     let server = TcpListener::bind(LISTEN_ADDRESS.to_owned()).await.unwrap();
     let clients: Arc<DashMap<String, RpcClient>> = Arc::new(DashMap::new());
+    dispatch::spawn_listener(clients.clone());
     let mut incoming = server.incoming();
     while let Some(stream) = incoming.next().await {
         let clients = clients.clone();
@@ -47,7 +52,8 @@ async fn start_client(
     clients: Arc<DashMap<String, RpcClient>>,
 ) {
     let connection = stream.unwrap();
-    println!("Socket connected: {}", connection.peer_addr().unwrap());
+    let ip = connection.peer_addr().unwrap().ip().to_string();
+    info!("Socket connected: {ip}");
     let ws_stream = accept_async(connection).await.expect("Failed to accept");
     let (mut write, mut read) = ws_stream.split();
     let (s, r) = unbounded::<Message>();
@@ -87,9 +93,7 @@ async fn start_client(
         .await
         .is_ok()
         {}
-        if let Some((_, client)) = clients_moved.remove(&id_moved) {
-            client.socket.close();
-        }
+        disconnect(&clients_moved, &id_moved).await;
     });
     let client = RpcClient {
         id: id.clone(),
@@ -97,14 +101,19 @@ async fn start_client(
         user: None,
         request_ids,
         heartbeat_tx: Arc::new(tx),
+        ip,
+        connected_at: chrono::Utc::now().timestamp_millis(),
+        session: None,
     };
     clients.insert(id.clone(), client);
-    while let Some(data) = read.next().await {
-        match data.unwrap() {
+    while let Some(Ok(data)) = read.next().await {
+        match data {
             Message::Binary(bin) => {
                 println!("Received binary data");
                 let response = handle_packet(bin, &clients, &id).await;
-                let client = clients.get(&id.clone()).unwrap();
+                let Some(client) = clients.get(&id.clone()) else {
+                    break;
+                };
                 client
                     .socket
                     .send(Message::Binary(
@@ -115,7 +124,9 @@ async fn start_client(
             }
             Message::Ping(bin) => {
                 println!("Received ping");
-                let client = clients.get(&id.clone()).unwrap();
+                let Some(client) = clients.get(&id.clone()) else {
+                    break;
+                };
                 client.socket.send(Message::Pong(bin)).await.unwrap();
             }
             Message::Close(_) => {
@@ -123,9 +134,19 @@ async fn start_client(
             }
             _ => {
                 println!("Received unknown message");
-                if let Some((_, client)) = clients.remove(&id.clone()) {
-                    client.socket.close();
-                }
+                break;
+            }
+        }
+    }
+    disconnect(&clients, &id).await;
+}
+
+async fn disconnect(clients: &Arc<DashMap<String, RpcClient>>, id: &String) {
+    if let Some((_, client)) = clients.remove(id) {
+        client.socket.close();
+        if let Some(session) = client.session {
+            if let Err(e) = session.delete().await {
+                warn!("Failed to delete session {}: {e}", session.id);
             }
         }
     }
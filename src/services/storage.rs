@@ -0,0 +1,329 @@
+use std::{
+    io::{Read, SeekFrom},
+    path::PathBuf,
+};
+
+use async_std::{fs, io::prelude::*, task::spawn_blocking};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use log::error;
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{Error, Result};
+
+use super::environment::{
+    S3_ACCESS_KEY, S3_BUCKET, S3_ENDPOINT, S3_REGION, S3_SECRET_KEY, STORAGE_BACKEND, STORAGE_PATH,
+};
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    // Reads at most `length` bytes starting at `offset`, fewer at the end
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>>;
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::new();
+
+pub fn connect() {
+    let storage: Box<dyn Storage> = match STORAGE_BACKEND.as_str() {
+        "local" => Box::new(LocalStorage {
+            root: PathBuf::from(&*STORAGE_PATH),
+        }),
+        "s3" => Box::new(S3Storage {
+            endpoint: S3_ENDPOINT.trim_end_matches('/').to_owned(),
+            bucket: S3_BUCKET.to_owned(),
+            region: S3_REGION.to_owned(),
+            access_key: S3_ACCESS_KEY.to_owned(),
+            secret_key: S3_SECRET_KEY.to_owned(),
+        }),
+        backend => panic!("Unknown storage backend: {backend}"),
+    };
+    if STORAGE.set(storage).is_err() {
+        panic!("Failed to set storage backend");
+    }
+}
+
+pub fn get_storage() -> &'static dyn Storage {
+    STORAGE
+        .get()
+        .expect("Failed to get storage backend")
+        .as_ref()
+}
+
+fn storage_error(e: impl std::fmt::Display) -> Error {
+    error!("Storage error: {e}");
+    Error::InternalError
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        // Keys are generated by the server, but never leave the storage root
+        if key
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == "..")
+        {
+            return Err(Error::NotFound);
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.map_err(storage_error)?;
+        }
+        fs::write(path, data).await.map_err(storage_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let mut file = match fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NotFound),
+            Err(e) => return Err(storage_error(e)),
+        };
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(storage_error)?;
+        let mut data = Vec::new();
+        file.take(length)
+            .read_to_end(&mut data)
+            .await
+            .map_err(storage_error)?;
+        Ok(data)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+}
+
+// Any S3-compatible service (e.g. MinIO) using path-style requests
+pub struct S3Storage {
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn uri_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+impl S3Storage {
+    fn host(&self) -> &str {
+        self.endpoint.split("://").last().unwrap_or(&self.endpoint)
+    }
+
+    // AWS Signature Version 4, returning the authorization header
+    fn sign(
+        &self,
+        method: &str,
+        path: &str,
+        payload_hash: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> String {
+        let host = self.host();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key_date = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let key_region = hmac(&key_date, &self.region);
+        let key_service = hmac(&key_region, "s3");
+        let key_signing = hmac(&key_service, "aws4_request");
+        let signature = hex::encode(hmac(&key_signing, &string_to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+            self.access_key
+        )
+    }
+
+    fn request(&self, method: &str, key: &str, payload: &[u8]) -> ureq::Request {
+        let path = uri_encode(&format!("/{}/{}", self.bucket, key));
+        let now = chrono::Utc::now();
+        let payload_hash = hex::encode(Sha256::digest(payload));
+        let authorization = self.sign(method, &path, &payload_hash, now);
+        ureq::request(method, &format!("{}{path}", self.endpoint))
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &now.format("%Y%m%dT%H%M%SZ").to_string())
+            .set("authorization", &authorization)
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let request = self.request("PUT", key, &data);
+        spawn_blocking(move || match request.send_bytes(&data) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let request = self.request("GET", key, &[]);
+        spawn_blocking(move || match request.call() {
+            Ok(response) => {
+                let mut data = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut data)
+                    .map_err(storage_error)?;
+                Ok(data)
+            }
+            Err(ureq::Error::Status(404, _)) => Err(Error::NotFound),
+            Err(e) => Err(storage_error(e)),
+        })
+        .await
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        // The range header is left unsigned, which S3 allows
+        let request = self
+            .request("GET", key, &[])
+            .set("range", &format!("bytes={offset}-{}", offset + length - 1));
+        spawn_blocking(move || match request.call() {
+            Ok(response) => {
+                let mut data = Vec::new();
+                response
+                    .into_reader()
+                    .take(length)
+                    .read_to_end(&mut data)
+                    .map_err(storage_error)?;
+                Ok(data)
+            }
+            // Starting past the end of the object
+            Err(ureq::Error::Status(416, _)) => Ok(Vec::new()),
+            Err(ureq::Error::Status(404, _)) => Err(Error::NotFound),
+            Err(e) => Err(storage_error(e)),
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let request = self.request("DELETE", key, &[]);
+        spawn_blocking(move || match request.call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn s3(endpoint: &str, bucket: &str) -> S3Storage {
+        S3Storage {
+            endpoint: endpoint.to_owned(),
+            bucket: bucket.to_owned(),
+            region: "us-east-1".to_owned(),
+            access_key: "minioadmin".to_owned(),
+            secret_key: "minioadmin".to_owned(),
+        }
+    }
+
+    #[test]
+    fn signs_requests_with_sigv4() {
+        let storage = s3("http://localhost:9000", "harmony");
+        let payload_hash = hex::encode(Sha256::digest(b"hello"));
+        assert_eq!(
+            payload_hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        let now = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let authorization = storage.sign("PUT", "/harmony/attachments/01HX", &payload_hash, now);
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=minioadmin/20240102/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=e47e1d1a98221c23766c8523574c66eaac8ece2397548bff6cdde3016677f4c4"
+        );
+    }
+
+    #[test]
+    fn encodes_keys_for_the_canonical_path() {
+        assert_eq!(uri_encode("/bucket/a b+c/ü"), "/bucket/a%20b%2Bc/%C3%BC");
+    }
+
+    async fn round_trip(storage: &dyn Storage) {
+        let key = format!("tests/{}", ulid::Ulid::new());
+        storage.put(&key, b"0123456789".to_vec()).await.unwrap();
+        assert_eq!(storage.get(&key).await.unwrap(), b"0123456789");
+        assert_eq!(storage.get_range(&key, 2, 3).await.unwrap(), b"234");
+        assert_eq!(storage.get_range(&key, 8, 10).await.unwrap(), b"89");
+        assert!(storage.get_range(&key, 10, 5).await.unwrap().is_empty());
+        storage.delete(&key).await.unwrap();
+        assert!(matches!(storage.get(&key).await, Err(Error::NotFound)));
+    }
+
+    #[async_std::test]
+    async fn local_storage_round_trip() {
+        let root = std::env::temp_dir().join(format!("harmony-{}", ulid::Ulid::new()));
+        round_trip(&LocalStorage { root: root.clone() }).await;
+        fs::remove_dir_all(root).await.unwrap();
+    }
+
+    // Needs an S3-compatible server with an existing bucket, e.g.
+    // MinIO on S3_TEST_ENDPOINT=http://localhost:9000
+    #[async_std::test]
+    #[ignore]
+    async fn s3_storage_round_trip() {
+        let endpoint = std::env::var("S3_TEST_ENDPOINT").expect("S3_TEST_ENDPOINT must be set");
+        let bucket = std::env::var("S3_TEST_BUCKET").unwrap_or_else(|_| "harmony".to_string());
+        round_trip(&s3(endpoint.trim_end_matches('/'), &bucket)).await;
+    }
+}
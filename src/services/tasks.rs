@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use async_std::task::{sleep, spawn};
+use log::{error, info, warn};
+
+use crate::{
+    errors::{Error, Result},
+    methods::{messages::send_message, Event, MessageDeletedEvent, ScheduledMessageFailedEvent},
+};
+
+use super::{
+    database::{
+        attachments::Attachment,
+        channels::{archive_inactive_threads, Channel},
+        messages::Message,
+        scheduled_messages::ScheduledMessage,
+        users::User,
+    },
+    dispatch::dispatch_event,
+    environment::{THREAD_ARCHIVE_AFTER, UPLOAD_EXPIRE_AFTER},
+};
+
+pub fn spawn_thread_archiver() {
+    spawn(async move {
+        loop {
+            let inactive_since =
+                chrono::Utc::now().timestamp_millis() - *THREAD_ARCHIVE_AFTER * 1000;
+            match archive_inactive_threads(inactive_since).await {
+                Ok(0) => {}
+                Ok(count) => info!("Archived {count} inactive threads"),
+                Err(e) => error!("Failed to archive threads: {e}"),
+            }
+            sleep(Duration::from_secs(60)).await;
+        }
+    });
+}
+
+// Removes uploads that were abandoned or never attached to anything
+pub fn spawn_upload_cleaner() {
+    spawn(async move {
+        loop {
+            let created_before =
+                chrono::Utc::now().timestamp_millis() - *UPLOAD_EXPIRE_AFTER * 1000;
+            match Attachment::delete_stale(created_before).await {
+                Ok(0) => {}
+                Ok(count) => info!("Deleted {count} stale uploads"),
+                Err(e) => error!("Failed to delete stale uploads: {e}"),
+            }
+            sleep(Duration::from_secs(60)).await;
+        }
+    });
+}
+
+// Sends due scheduled messages and deletes expired ones
+pub fn spawn_message_scheduler() {
+    spawn(async move {
+        loop {
+            let now = chrono::Utc::now().timestamp_millis();
+            if let Err(e) = send_scheduled_messages(now).await {
+                error!("Failed to send scheduled messages: {e}");
+            }
+            if let Err(e) = delete_expired_messages(now).await {
+                error!("Failed to delete expired messages: {e}");
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+async fn send_scheduled_messages(now: i64) -> Result<()> {
+    while let Some(scheduled) = ScheduledMessage::claim_due(now).await? {
+        // A previous claim may have sent the message without completing
+        let sent = match &scheduled.message_id {
+            Some(message_id) => Message::exists(message_id).await?,
+            None => false,
+        };
+        if !sent {
+            // Uploads claimed by an attempt that never stored the message
+            if let Some(message_id) = &scheduled.message_id {
+                Attachment::release(message_id).await?;
+            }
+            // The author may have lost access since the message was scheduled
+            let result = match User::get(&scheduled.author_id).await {
+                Ok(user) => send_message(&user, &scheduled.draft, Some(&scheduled))
+                    .await
+                    .map(|_| ()),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {}
+                // Sent once the author's cooldown in the channel is over
+                Err(Error::SlowmodeActive { retry_after }) => {
+                    scheduled.postpone(now + retry_after).await?;
+                    continue;
+                }
+                // Left for the lease to expire, after which it is retried
+                Err(Error::DatabaseError { message }) => {
+                    error!(
+                        "Failed to send scheduled message {}: {message}",
+                        scheduled.id
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Dropped scheduled message {}: {e}", scheduled.id);
+                    Attachment::release_reserved(&scheduled.id).await?;
+                    dispatch_event(
+                        vec![scheduled.author_id.clone()],
+                        Event::ScheduledMessageFailed(ScheduledMessageFailedEvent {
+                            id: scheduled.id.clone(),
+                            channel_id: scheduled.draft.channel_id.clone(),
+                            error: e,
+                        }),
+                    )
+                    .await?;
+                }
+            }
+        }
+        scheduled.complete().await?;
+    }
+    Ok(())
+}
+
+async fn delete_expired_messages(now: i64) -> Result<()> {
+    for message in Message::get_expired(now).await? {
+        // Another instance may have deleted it already
+        let Ok(message) = message.purge().await else {
+            continue;
+        };
+        // The channel may be gone, in which case nobody is left to tell
+        let viewers = match Channel::get(&message.channel_id).await {
+            Ok(channel) => channel.get_viewers().await,
+            Err(e) => Err(e),
+        };
+        let viewers = match viewers {
+            Ok(viewers) => viewers,
+            Err(Error::NotFound) => continue,
+            Err(e) => {
+                warn!("Failed to notify about expired message {}: {e}", message.id);
+                continue;
+            }
+        };
+        dispatch_event(
+            viewers,
+            Event::MessageDeleted(MessageDeletedEvent {
+                message_id: message.id,
+                channel_id: message.channel_id,
+            }),
+        )
+        .await?;
+    }
+    Ok(())
+}